@@ -1 +1 @@
-mod spef_parser;
\ No newline at end of file
+pub mod spef_parser;
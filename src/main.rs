@@ -1,4 +1,4 @@
-mod spef_parser;
+use spef_parser::spef_parser;
 
 fn main() {
     let spef_file_str = "/home/immelon/projects/iPD/src/database/manager/parser/spef/spef-parser/aes_simple.spef";
@@ -7,6 +7,9 @@ fn main() {
         Ok(exchange_data) => {
             println!("Parsed {spef_file_str} successfully\n, exchange_data: {exchange_data:#?}");
         }
-        Err(_) => todo!(),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
     }
 }
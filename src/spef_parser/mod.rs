@@ -1,345 +1,1160 @@
 pub mod spef_data;
+pub mod spef_error;
+mod spef_lexer;
+pub mod spef_stream;
 
-use pest::iterators::Pair;
-use pest::Parser;
+use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
-use std::fs;
+use spef_error::{SpefError, SpefErrorInfo};
+use std::sync::Arc;
 
 #[derive(Parser)]
-#[grammar = "spef_parser/grammar/spef.pest"]
-
+#[grammar = "spef_parser/grammar/spef_new.pest"]
 struct SpefParser;
 
-/// process float data.
-fn process_float(pair: Pair<Rule>) -> Result<f64, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
+/// locate an error at the pest pair that caused it.
+fn pair_error_info(file_name: &str, pair: &Pair<Rule>, message: &str) -> SpefErrorInfo {
+    let (line_no, column) = pair.line_col();
+    SpefErrorInfo::new(file_name, line_no, column, pair.as_str(), message)
+}
+
+/// convert a pest parse failure into a located syntax error.
+fn pest_error_to_spef_error(file_name: &str, err: pest::error::Error<Rule>) -> SpefError {
+    let (line_no, column) = match err.line_col {
+        pest::error::LineColLocation::Pos(line_col) => line_col,
+        pest::error::LineColLocation::Span(start, _) => start,
+    };
+    SpefError::SYNTAX(SpefErrorInfo::new(file_name, line_no, column, err.line(), &err.variant.message()))
+}
 
+/// take the next inner pair of an entry, a missing pair is reported on the whole entry.
+fn next_pair<'a>(
+    inner_rules: &mut Pairs<'a, Rule>,
+    parent: &Pair<'a, Rule>,
+    file_name: &str,
+    what: &str,
+) -> Result<Pair<'a, Rule>, SpefError> {
+    match inner_rules.next() {
+        Some(pair) => Ok(pair),
+        None => Err(SpefError::SYNTAX(pair_error_info(file_name, parent, &format!("Missing {what}")))),
+    }
+}
+
+/// process float data.
+fn process_float(pair: Pair<Rule>, file_name: &str) -> Result<f64, SpefError> {
     // remove the preceding "*" before the index
-    let mut pair_str = pair.as_str();
-    let clearned_str: String = pair_str.chars().filter(|&c| c != '*').collect();
+    let clearned_str: String = pair.as_str().chars().filter(|&c| c != '*').collect();
 
     match clearned_str.parse::<f64>() {
         Ok(value) => Ok(value),
-        Err(_) => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Failed to parse float".into() },
-            pair_clone.as_span(),
-        )),
+        Err(_) => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse float"))),
     }
 }
 
-/// process xy coordinates
-fn process_coordinates(pair: Pair<Rule>) -> Result<(f64, f64), pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-
-    let tuple_pair = pair.clone();
-
-    let mut inner_rules = pair_clone.into_inner();
-    let x_coordiante_pair = inner_rules.next();
-    let y_coordinate_pair = inner_rules.next();
-
-    match (x_coordiante_pair, y_coordinate_pair) {
-        (Some(x_float_pair), Some(y_float_pair)) => {
-            let x_float = process_float(x_float_pair);
-            let y_float = process_float(y_float_pair);
-            match (x_float, y_float) {
-                (Ok(x), Ok(y)) => Ok((x, y)),
-                _ => Err(pest::error::Error::new_from_span(
-                    pest::error::ErrorVariant::CustomError { message: "Failed to parse float".into() },
-                    tuple_pair.as_span(),
-                )),
-            }
-        }
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Failed to parse xy_coordinates".into() },
-            tuple_pair.as_span(),
-        )),
+/// process a parasitic value, either one number or a min:typ:max triplet.
+fn process_par_value(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::SpefParValue, SpefError> {
+    let values: Vec<f64> =
+        pair.clone().into_inner().map(|num_pair| process_float(num_pair, file_name)).collect::<Result<_, _>>()?;
+
+    match values.as_slice() {
+        [value] => Ok(spef_data::SpefParValue::SCALAR(*value)),
+        [min, typ, max] => Ok(spef_data::SpefParValue::TRIPLET(*min, *typ, *max)),
+        _ => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse value"))),
     }
 }
 
-/// process string text data not include quote(All string values in spef file are not quoted).
-fn process_string(pair: Pair<Rule>) -> Result<String, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-    match pair_clone.as_str().parse::<String>() {
+/// process index data such as *25 or 3.
+fn process_index(pair: Pair<Rule>, file_name: &str) -> Result<usize, SpefError> {
+    match pair.as_str().trim_start_matches('*').parse::<usize>() {
         Ok(value) => Ok(value),
-        Err(_) => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Failed to parse string".into() },
-            pair_clone.as_span(),
-        )),
+        Err(_) => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse index"))),
     }
 }
 
+/// process xy coordinates
+fn process_coordinates(pair: Pair<Rule>, file_name: &str) -> Result<(f64, f64), SpefError> {
+    let mut inner_rules = pair.clone().into_inner();
+    let x_float_pair = next_pair(&mut inner_rules, &pair, file_name, "x coordinate")?;
+    let y_float_pair = next_pair(&mut inner_rules, &pair, file_name, "y coordinate")?;
+
+    Ok((process_float(x_float_pair, file_name)?, process_float(y_float_pair, file_name)?))
+}
+
+/// process string text data not include quote(All string values in spef file are not quoted).
+fn process_string(pair: Pair<Rule>) -> String {
+    pair.as_str().to_string()
+}
+
+/// process a quoted string without its quotes
+fn process_quoted_string(pair: Pair<Rule>) -> String {
+    pair.into_inner().next().map(process_string).unwrap_or_default()
+}
+
+/// process one header value, a quoted string or a bare token such as a unit
+fn process_header_value(pair: Pair<Rule>) -> String {
+    match pair.as_rule() {
+        Rule::string => process_quoted_string(pair),
+        _ => process_string(pair),
+    }
+}
+
+/// process a node name, split at the pin delimiter of the file, net_names tell internal nodes from other nets
+fn process_node_ref(pair: Pair<Rule>, delimiter: char, net_names: &[spef_data::SpefNameRef]) -> spef_data::SpefNodeRef {
+    spef_data::SpefNodeRef::from_spef_name(pair.as_str(), delimiter, net_names)
+}
+
 /// process connection direction enum
-fn process_conn_dir_enum(pair: Pair<Rule>) -> Result<spef_data::ConnectionDirection, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
+fn process_conn_dir_enum(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::ConnectionDirection, SpefError> {
     match pair.as_str() {
         "I" => Ok(spef_data::ConnectionDirection::INPUT),
         "O" => Ok(spef_data::ConnectionDirection::OUTPUT),
         "B" => Ok(spef_data::ConnectionDirection::INOUT),
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Failed to parse connection direction".into() },
-            pair_clone.as_span(),
-        )),
+        _ => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse connection direction"))),
     }
 }
 
 /// process connection type enum
-fn process_conn_type_enum(pair: Pair<Rule>) -> Result<spef_data::ConnectionType, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
+fn process_conn_type_enum(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::ConnectionType, SpefError> {
     match pair.as_str() {
         "*I" => Ok(spef_data::ConnectionType::INTERNAL),
         "*P" => Ok(spef_data::ConnectionType::EXTERNAL),
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Failed to parse connection type".into() },
-            pair_clone.as_span(),
-        )),
+        _ => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse connection type"))),
     }
 }
 
 /// process section entry
-fn process_section_entry(pair: Pair<Rule>) -> Result<spef_data::SpefSectionEntry, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
+fn process_section_entry(pair: Pair<Rule>, file_name: &Arc<str>) -> Result<spef_data::SpefSectionEntry, SpefError> {
     let line_no = pair.line_col().0;
 
-    let mut inner_rules = pair_clone.into_inner();
-
-    let section_name_pair = inner_rules.next().unwrap();
-
-    let section_name_result = process_string(section_name_pair);
-
-    match section_name_result {
-        Ok(result) => {
-            let section_type: spef_data::SectionType = match result {
-                s if s == "NAME_MAP" => spef_data::SectionType::NAMEMAP,
-                s if s == "PORTS" => spef_data::SectionType::PORTS,
-                s if s == "CONN" => spef_data::SectionType::CONN,
-                s if s == "CAP" => spef_data::SectionType::CAP,
-                s if s == "RES" => spef_data::SectionType::RES,
-                s if s == "END" => spef_data::SectionType::END,
-                _ => {
-                    // 处理未知规则的情况
-                    return Err(pest::error::Error::new_from_span(
-                        pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-                        pair.as_span(),
-                    ));
-                }
-            };
-            Ok(spef_data::SpefSectionEntry::new("tbd", line_no, section_type))
-        },
-        Err(_) => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-            pair.as_span(),
-        )),
-    }
+    let mut inner_rules = pair.clone().into_inner();
+    let section_name_pair = next_pair(&mut inner_rules, &pair, file_name, "section name")?;
+
+    let section_type = match section_name_pair.as_str() {
+        "NAME_MAP" => spef_data::SectionType::NAMEMAP,
+        "PORTS" => spef_data::SectionType::PORTS,
+        "PHYSICAL_PORTS" => spef_data::SectionType::PHYSICALPORTS,
+        "CONN" => spef_data::SectionType::CONN,
+        "CAP" => spef_data::SectionType::CAP,
+        "RES" => spef_data::SectionType::RES,
+        "INDUC" => spef_data::SectionType::INDUC,
+        "LOADS" => spef_data::SectionType::LOADS,
+        "END" => spef_data::SectionType::END,
+        _ => return Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Unknown section"))),
+    };
+    Ok(spef_data::SpefSectionEntry::new(file_name, line_no, section_type))
 }
 
 /// process pest pairs that matches spef header section entry
-fn process_header_entry(pair: Pair<Rule>) -> Result<spef_data::SpefHeaderEntry, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-    let line_no = pair_clone.line_col().0;
+fn process_header_entry(pair: Pair<Rule>, file_name: &Arc<str>) -> Result<spef_data::SpefHeaderEntry, SpefError> {
+    let line_no = pair.line_col().0;
 
-    let mut inner_rules = pair_clone.into_inner();
-    // println!("{inner_rules:#?}");
+    let mut inner_rules = pair.clone().into_inner();
 
-    // header_keyword_pair and header_value_pair are string pairs
-    let header_keyword_pair = inner_rules.next().unwrap();
-    let header_value_pair = inner_rules.next().unwrap();
+    // the keyword is followed by one or more values, quoted strings are kept without their quotes
+    let header_keyword_pair = next_pair(&mut inner_rules, &pair, file_name, "header keyword")?;
+    let header_key = process_string(header_keyword_pair);
+    let header_values: Vec<String> = inner_rules.map(process_header_value).collect();
+    if header_values.is_empty() {
+        return Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Missing header value")));
+    }
 
-    let keyword_pair_result = process_string(header_keyword_pair);
-    let value_pair_result = process_string(header_value_pair);
+    Ok(spef_data::SpefHeaderEntry::new(file_name, line_no, header_key, header_values))
+}
 
-    match (keyword_pair_result, value_pair_result) {
-        (Ok(header_key), Ok(header_value)) => {
-            Ok(spef_data::SpefHeaderEntry::new("tbd", line_no, header_key, header_value))
-        }
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-            pair.as_span(),
-        )),
+/// process pest pairs that matches spef namemap section entry
+fn process_namemap_entry(pair: Pair<Rule>, file_name: &Arc<str>) -> Result<spef_data::SpefNameMapEntry, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    // name_index_pair is index pair, name_value_pair is string pair
+    let name_index_pair = next_pair(&mut inner_rules, &pair, file_name, "name index")?;
+    let name_value_pair = next_pair(&mut inner_rules, &pair, file_name, "name")?;
+
+    let name_index = process_index(name_index_pair, file_name)?;
+    let name = process_string(name_value_pair);
+
+    Ok(spef_data::SpefNameMapEntry::new(file_name, line_no, name_index, &name))
+}
+
+/// whether a name_map_entry pair maps a name rather than being a port on a mapped net,
+/// a direction with no port attributes after it is a name in *NAME_MAP only
+fn is_name_map_line(pair: &Pair<Rule>, section: &spef_data::SectionType) -> bool {
+    let mut inner_rules = pair.clone().into_inner().skip(1);
+    match (inner_rules.next(), inner_rules.next()) {
+        (Some(name_pair), None) => name_pair.as_rule() == Rule::str_name || *section == spef_data::SectionType::NAMEMAP,
+        _ => false,
     }
 }
 
-/// process pest pairs that matches spef namemap section entry
-fn process_namemap_entry(pair: Pair<Rule>) -> Result<spef_data::SpefNameMapEntry, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-    let line_no = pair_clone.line_col().0;
+/// process pest pairs that matches *POWER_NETS or *GROUND_NETS net names...
+fn process_supply_nets_entry(pair: Pair<Rule>) -> Vec<String> {
+    pair.into_inner().map(process_string).collect()
+}
+
+/// process pest pairs that matches *DEFINE/*PDEFINE inst_name... "entity"
+fn process_define_entry(pair: Pair<Rule>, file_name: &Arc<str>) -> Result<spef_data::SpefDefineEntry, SpefError> {
+    let line_no = pair.line_col().0;
 
-    let mut inner_rules = pair_clone.into_inner();
-    // println!("{inner_rules:#?}");
+    let mut inner_rules = pair.clone().into_inner();
+    let keyword_pair = next_pair(&mut inner_rules, &pair, file_name, "*DEFINE or *PDEFINE")?;
 
-    // name_index_pair is float pair, name_value_pair is string pair
-    let name_index_pair = inner_rules.next().unwrap();
-    let name_value_pair = inner_rules.next().unwrap();
+    let mut instances = Vec::new();
+    let mut entity = None;
+    for inner_pair in inner_rules {
+        match inner_pair.as_rule() {
+            Rule::string => entity = Some(process_quoted_string(inner_pair)),
+            _ => instances.push(process_string(inner_pair)),
+        }
+    }
+    let Some(entity) = entity else {
+        return Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Missing entity")));
+    };
+
+    let mut define_entry = spef_data::SpefDefineEntry::new(file_name, line_no, instances, entity);
+    define_entry.set_physical(keyword_pair.as_str() == "*PDEFINE");
+    Ok(define_entry)
+}
 
-    let index_pair_result = process_float(name_index_pair);
-    let value_pair_result = process_string(name_value_pair);
+/// optional *C, *L, *S and *D attributes of a *CONN or *PORTS line
+#[derive(Default)]
+struct ConnAttributes {
+    coordinates: Option<(f64, f64)>,
+    load: Option<spef_data::SpefParValue>,
+    slews: Option<spef_data::SpefSlews>,
+    driving_cell: Option<String>,
+}
+
+/// process *S rise fall [rise_threshold fall_threshold]
+fn process_slews(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::SpefSlews, SpefError> {
+    let values: Vec<spef_data::SpefParValue> = pair
+        .clone()
+        .into_inner()
+        .map(|value_pair| process_par_value(value_pair, file_name))
+        .collect::<Result<_, _>>()?;
+
+    match values.as_slice() {
+        [rise, fall] => Ok(spef_data::SpefSlews { rise: *rise, fall: *fall, thresholds: None }),
+        [rise, fall, low, high] => {
+            Ok(spef_data::SpefSlews { rise: *rise, fall: *fall, thresholds: Some((*low, *high)) })
+        }
+        _ => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse slews"))),
+    }
+}
 
-    match (index_pair_result, value_pair_result) {
-        (Ok(name_index), Ok(name_pair)) => {
-            Ok(spef_data::SpefNameMapEntry::new("tbd", line_no, name_index as usize, &name_pair))
+/// process the attributes that follow a connection or a port, each may come once and in any order
+fn process_conn_attributes(attr_pairs: Pairs<Rule>, file_name: &str) -> Result<ConnAttributes, SpefError> {
+    let mut attributes = ConnAttributes::default();
+    for attr_pair in attr_pairs {
+        let duplicated = match attr_pair.as_rule() {
+            Rule::xy_coordinates => {
+                attributes.coordinates.replace(process_coordinates(attr_pair.clone(), file_name)?).is_some()
+            }
+            Rule::cap_load => {
+                let mut load_rules = attr_pair.clone().into_inner();
+                let load_pair = next_pair(&mut load_rules, &attr_pair, file_name, "*L load")?;
+                attributes.load.replace(process_par_value(load_pair, file_name)?).is_some()
+            }
+            Rule::slews => attributes.slews.replace(process_slews(attr_pair.clone(), file_name)?).is_some(),
+            Rule::driving_cell => {
+                let mut driver_rules = attr_pair.clone().into_inner();
+                let driver_pair = next_pair(&mut driver_rules, &attr_pair, file_name, "*D driving cell")?;
+                attributes.driving_cell.replace(process_string(driver_pair)).is_some()
+            }
+            _ => return Err(SpefError::SYNTAX(pair_error_info(file_name, &attr_pair, "Unknown connection attribute"))),
+        };
+        if duplicated {
+            return Err(SpefError::SEMANTIC(pair_error_info(file_name, &attr_pair, "Duplicated connection attribute")));
         }
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-            pair.as_span(),
-        )),
     }
+    Ok(attributes)
 }
 
 /// process pest pairs that matches spef ports section entry
-fn process_port_entry(pair: Pair<Rule>) -> Result<spef_data::SpefPortEntry, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-    let line_no = pair_clone.line_col().0;
+fn process_port_entry(pair: Pair<Rule>, file_name: &Arc<str>) -> Result<spef_data::SpefPortEntry, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    let name_pair = next_pair(&mut inner_rules, &pair, file_name, "port name")?;
+    let conn_dir_pair = next_pair(&mut inner_rules, &pair, file_name, "port direction")?;
 
-    let mut inner_rules = pair_clone.into_inner();
-    // println!("{inner_rules:#?}");
+    let name = process_string(name_pair);
+    let direction = process_conn_dir_enum(conn_dir_pair, file_name)?;
 
-    // name_index_pair is float pair, name_value_pair is string pair
-    let name_index_pair = inner_rules.next().unwrap();
-    let conn_dir_pair = inner_rules.next().unwrap();
-    let coordinates_pair = inner_rules.next().unwrap();
+    let mut port_entry = spef_data::SpefPortEntry::new(file_name, line_no, name, direction);
 
-    let index_pair_result = process_float(name_index_pair);
-    let dir_pair_result = process_conn_dir_enum(conn_dir_pair);
-    let coor_pair_result = process_coordinates(coordinates_pair);
+    // the remaining pairs are the optional *C, *L, *S and *D attributes in any order
+    let attributes = process_conn_attributes(inner_rules, file_name)?;
+    port_entry.coordinates = attributes.coordinates;
+    port_entry.load = attributes.load;
+    port_entry.slews = attributes.slews;
+    port_entry.driving_cell = attributes.driving_cell;
 
-    match (index_pair_result, dir_pair_result, coor_pair_result) {
-        (Ok(index), Ok(direction), Ok(coordinates)) => {
-            Ok(spef_data::SpefPortEntry::new("tbd", line_no, index.to_string(), direction, coordinates))
+    Ok(port_entry)
+}
+
+/// process pest pairs that matches spef dnet section entry, creating a SpefNet
+fn process_dnet_entry(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::SpefNet, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    let keyword_pair = next_pair(&mut inner_rules, &pair, file_name, "*D_NET or *D_PNET")?;
+    let name_pair = next_pair(&mut inner_rules, &pair, file_name, "net name")?;
+    let cap_pair = next_pair(&mut inner_rules, &pair, file_name, "net total cap")?;
+
+    let name = process_string(name_pair);
+    let cap = process_par_value(cap_pair, file_name)?;
+
+    let mut net = spef_data::SpefNet::new(line_no, name, cap);
+    net.set_physical(keyword_pair.as_str() == "*D_PNET");
+    Ok(net)
+}
+
+/// process pest pairs that matches spef conn section entry
+fn process_conn_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefConnEntry, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    let conn_type_pair = next_pair(&mut inner_rules, &pair, file_name, "connection type")?;
+    let pin_name_pair = next_pair(&mut inner_rules, &pair, file_name, "pin name")?;
+    let conn_dir_pair = next_pair(&mut inner_rules, &pair, file_name, "connection direction")?;
+
+    let conn_type = process_conn_type_enum(conn_type_pair, file_name)?;
+    // *P is a top level port, *I is always an instance pin even when the pin name is a number
+    let node = match (&conn_type, process_node_ref(pin_name_pair.clone(), delimiter, net_names)) {
+        (spef_data::ConnectionType::EXTERNAL, _) => {
+            spef_data::SpefNodeRef::PORT(spef_data::SpefNameRef::from_spef_name(pin_name_pair.as_str()))
+        }
+        (_, spef_data::SpefNodeRef::PORT(_)) => {
+            let message = "*I connection needs an instance pin";
+            return Err(SpefError::SEMANTIC(pair_error_info(file_name, &pin_name_pair, message)));
+        }
+        (_, spef_data::SpefNodeRef::INTERNAL(instance, pin) | spef_data::SpefNodeRef::NETNODE(instance, pin)) => {
+            spef_data::SpefNodeRef::PIN(instance, pin.to_string())
+        }
+        (_, node) => node,
+    };
+    let conn_dir = process_conn_dir_enum(conn_dir_pair, file_name)?;
+
+    let mut current_conn = spef_data::SpefConnEntry::new(file_name, line_no, conn_type, conn_dir, node);
+
+    // the remaining pairs are the optional *C, *L, *S and *D attributes in any order
+    let attributes = process_conn_attributes(inner_rules, file_name)?;
+    current_conn.coordinates = attributes.coordinates;
+    current_conn.load = attributes.load;
+    current_conn.slews = attributes.slews;
+    current_conn.driving_cell = attributes.driving_cell;
+
+    Ok(current_conn)
+}
+
+/// process pest pairs that matches spef conn section *N entry, the node has to be an internal node of the net
+fn process_internal_node_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefInternalNode, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    let node_pair = next_pair(&mut inner_rules, &pair, file_name, "internal node")?;
+    let coordinates_pair = next_pair(&mut inner_rules, &pair, file_name, "internal node coordinates")?;
+
+    let node = match process_node_ref(node_pair.clone(), delimiter, net_names) {
+        node @ spef_data::SpefNodeRef::INTERNAL(..) => node,
+        _ => {
+            let message = "*N needs an internal node of the net";
+            return Err(SpefError::SEMANTIC(pair_error_info(file_name, &node_pair, message)));
+        }
+    };
+    let coordinates = process_coordinates(coordinates_pair, file_name)?;
+
+    Ok(spef_data::SpefInternalNode::new(file_name, line_no, node, coordinates))
+}
+
+/// process the `// $llx=.. $lly=.. $urx=.. $ury=.. $lvl=..` comment StarRC and QRC put after a line,
+/// None when the line has no such comment. Other `$` values of the comment are skipped.
+fn process_vendor_annotation(
+    pair: &Pair<Rule>,
+    file_name: &str,
+) -> Result<Option<spef_data::SpefVendorGeometry>, SpefError> {
+    // comments are skipped by the grammar, so read the rest of the line after the last token of the entry,
+    // the entry and attribute spans run over the comment when optional tokens may follow
+    let entry_end =
+        pair.clone().into_inner().flatten().last().map_or(pair.as_span().end(), |token| token.as_span().end());
+    let line_rest = pair.get_input()[entry_end..].split('\n').next().unwrap_or("");
+    parse_vendor_annotation(line_rest).map_err(|token| {
+        SpefError::SEMANTIC(pair_error_info(file_name, pair, &format!("Invalid vendor annotation {token}")))
+    })
+}
+
+/// layer and bounding box of the text after a line's last token, the error is the annotation that is no number
+fn parse_vendor_annotation(line_rest: &str) -> Result<Option<spef_data::SpefVendorGeometry>, &str> {
+    let Some(comment) = line_rest.trim_start().strip_prefix("//") else {
+        return Ok(None);
+    };
+
+    let (mut llx, mut lly, mut urx, mut ury, mut layer) = (None, None, None, None, None);
+    for token in comment.split_whitespace() {
+        let Some((key, value)) = token.strip_prefix('$').and_then(|token| token.split_once('=')) else {
+            continue;
+        };
+        let coordinate = match key {
+            "llx" => &mut llx,
+            "lly" => &mut lly,
+            "urx" => &mut urx,
+            "ury" => &mut ury,
+            "lvl" => {
+                layer = Some(value.parse::<usize>().map_err(|_| token)?);
+                continue;
+            }
+            _ => continue,
+        };
+        *coordinate = Some(value.parse::<f64>().map_err(|_| token)?);
+    }
+
+    if layer.is_none() && llx.is_none() && lly.is_none() && urx.is_none() && ury.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(spef_data::SpefVendorGeometry { layer, ll_coordinate: llx.zip(lly), ur_coordinate: urx.zip(ury) }))
+}
+
+/// process pest pairs that matches spef cap section entry, one node for a ground cap and two for a coupling cap
+fn process_cap_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefCapEntry, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    let index_pair = next_pair(&mut inner_rules, &pair, file_name, "cap index")?;
+    let node1_pair = next_pair(&mut inner_rules, &pair, file_name, "cap node")?;
+    let (node2_pair, value_pair) = match (inner_rules.next(), inner_rules.next()) {
+        (Some(node2_pair), Some(value_pair)) => (Some(node2_pair), value_pair),
+        (Some(value_pair), None) => (None, value_pair),
+        _ => return Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Missing cap value"))),
+    };
+
+    let index = process_index(index_pair, file_name)?;
+    let node1 = process_node_ref(node1_pair, delimiter, net_names);
+    let node2 = node2_pair.map(|node2_pair| process_node_ref(node2_pair, delimiter, net_names));
+    let value = process_par_value(value_pair, file_name)?;
+
+    Ok(spef_data::SpefCapEntry::new(file_name, line_no, index, node1, node2, value))
+}
+
+/// process the index, both nodes and the value of a *RES or *INDUC line
+fn process_two_node_entry(
+    pair: Pair<Rule>,
+    file_name: &str,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+    element_name: &str,
+) -> Result<(usize, spef_data::SpefNodeRef, spef_data::SpefNodeRef, spef_data::SpefParValue), SpefError> {
+    let mut inner_rules = pair.clone().into_inner();
+
+    let index_pair = next_pair(&mut inner_rules, &pair, file_name, "index")?;
+    let node1_pair = next_pair(&mut inner_rules, &pair, file_name, "node")?;
+    let (node2_pair, value_pair) = match (inner_rules.next(), inner_rules.next()) {
+        (Some(node2_pair), Some(value_pair)) => (node2_pair, value_pair),
+        _ => {
+            let message = format!("{} needs two nodes", element_name);
+            return Err(SpefError::SEMANTIC(pair_error_info(file_name, &pair, &message)));
+        }
+    };
+
+    let index = process_index(index_pair, file_name)?;
+    let node1 = process_node_ref(node1_pair, delimiter, net_names);
+    let node2 = process_node_ref(node2_pair, delimiter, net_names);
+    let value = process_par_value(value_pair, file_name)?;
+
+    Ok((index, node1, node2, value))
+}
+
+/// process pest pairs that matches spef res section entry
+fn process_res_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefResEntry, SpefError> {
+    let line_no = pair.line_col().0;
+    let (index, node1, node2, value) = process_two_node_entry(pair, file_name, delimiter, net_names, "Resistor")?;
+
+    Ok(spef_data::SpefResEntry::new(file_name, line_no, index, node1, node2, value))
+}
+
+/// process pest pairs that matches spef induc section entry, the value stays in the *L_UNIT of the file
+fn process_induc_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefInducEntry, SpefError> {
+    let line_no = pair.line_col().0;
+    let (index, node1, node2, value) = process_two_node_entry(pair, file_name, delimiter, net_names, "Inductor")?;
+
+    Ok(spef_data::SpefInducEntry::new(file_name, line_no, index, node1, node2, value))
+}
+
+/// process the values of *T_UNIT, *C_UNIT, *R_UNIT or *L_UNIT such as 1 PF or 10 UH
+fn process_unit(
+    pair: &Pair<Rule>,
+    unit_values: &[&str],
+    file_name: &str,
+    quantity: spef_data::SpefQuantity,
+) -> Result<spef_data::SpefUnit, SpefError> {
+    let multiplier = unit_values.first().and_then(|multiplier| multiplier.parse::<f64>().ok());
+    let unit_name = unit_values.get(1).and_then(|unit_name| spef_data::SpefUnitName::from_keyword(unit_name));
+
+    match (multiplier, unit_name) {
+        (Some(multiplier), Some(unit_name)) if multiplier > 0.0 && unit_name.quantity() == quantity => {
+            Ok(spef_data::SpefUnit::new(multiplier, unit_name))
+        }
+        _ => {
+            let message = match quantity {
+                spef_data::SpefQuantity::TIME => "Unknown *T_UNIT, expected NS or PS",
+                spef_data::SpefQuantity::CAPACITANCE => "Unknown *C_UNIT, expected PF or FF",
+                spef_data::SpefQuantity::RESISTANCE => "Unknown *R_UNIT, expected OHM or KOHM",
+                spef_data::SpefQuantity::INDUCTANCE => "Unknown *L_UNIT, expected HENRY, MH or UH",
+            };
+            Err(SpefError::UNIT(pair_error_info(file_name, pair, message)))
         }
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-            pair.as_span(),
-        )),
     }
 }
 
-/// process pest pairs that matches spef dnet section entry, creating a SpefNet
-fn process_dnet_entry<'a>(
-    pair: Pair<'a, Rule>,
-    current_net: &'a mut spef_data::SpefNet,
-) -> Result<spef_data::SpefNet, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-    let line_no = pair_clone.line_col().0;
-
-    let mut inner_rules = pair_clone.into_inner();
-
-    let name_pair = inner_rules.next().unwrap();
-    let cap_pair = inner_rules.next().unwrap();
-
-    let name_pair_result = process_string(name_pair);
-    let cap_pair_result = process_float(cap_pair);
-
-    match (name_pair_result, cap_pair_result) {
-        (Ok(name), Ok(cap)) => {
-            current_net.name = name;
-            current_net.line_no = line_no;
-            current_net.lcap = cap;
-            Ok(current_net.clone())
+const DESIGN_FLOW_KEYWORDS: [&str; 10] = [
+    "EXTERNAL_LOADS",
+    "EXTERNAL_SLEWS",
+    "FULL_CONNECTIVITY",
+    "MISSING_NETS",
+    "ROUTING_CONFIDENCE",
+    "ADDITIONAL_LOAD",
+    "NAME_SCOPE",
+    "PIN_CAP",
+    "SLEW_THRESHOLDS",
+    "COUPLING",
+];
+
+/// process one quoted *DESIGN_FLOW value such as "PIN_CAP NONE"
+fn process_design_flow(
+    pair: &Pair<Rule>,
+    flow_value: &str,
+    file_name: &str,
+) -> Result<spef_data::SpefDesignFlow, SpefError> {
+    use spef_data::{SpefDesignFlow, SpefNameScope, SpefNetlistType, SpefPinCap};
+
+    let flow_fields: Vec<&str> = flow_value.split_whitespace().collect();
+    let design_flow = match flow_fields.as_slice() {
+        ["EXTERNAL_LOADS"] => Some(SpefDesignFlow::EXTERNALLOADS),
+        ["EXTERNAL_SLEWS"] => Some(SpefDesignFlow::EXTERNALSLEWS),
+        ["FULL_CONNECTIVITY"] => Some(SpefDesignFlow::FULLCONNECTIVITY),
+        ["MISSING_NETS"] => Some(SpefDesignFlow::MISSINGNETS),
+        ["NETLIST_TYPE_VERILOG"] => Some(SpefDesignFlow::NETLISTTYPE(SpefNetlistType::VERILOG)),
+        ["NETLIST_TYPE_VHDL87"] => Some(SpefDesignFlow::NETLISTTYPE(SpefNetlistType::VHDL87)),
+        ["NETLIST_TYPE_VHDL93"] => Some(SpefDesignFlow::NETLISTTYPE(SpefNetlistType::VHDL93)),
+        ["NETLIST_TYPE_EDIF"] => Some(SpefDesignFlow::NETLISTTYPE(SpefNetlistType::EDIF)),
+        ["ROUTING_CONFIDENCE", confidence] => confidence.parse().ok().map(SpefDesignFlow::ROUTINGCONFIDENCE),
+        ["ADDITIONAL_LOAD"] => Some(SpefDesignFlow::ADDITIONALLOAD),
+        ["NAME_SCOPE", "LOCAL"] => Some(SpefDesignFlow::NAMESCOPE(SpefNameScope::LOCAL)),
+        ["NAME_SCOPE", "FLAT"] => Some(SpefDesignFlow::NAMESCOPE(SpefNameScope::FLAT)),
+        ["PIN_CAP", "NONE"] => Some(SpefDesignFlow::PINCAP(SpefPinCap::NONE)),
+        ["PIN_CAP", "INPUT_OUTPUT"] => Some(SpefDesignFlow::PINCAP(SpefPinCap::INPUTOUTPUT)),
+        ["PIN_CAP", "INPUT_ONLY"] => Some(SpefDesignFlow::PINCAP(SpefPinCap::INPUTONLY)),
+        ["SLEW_THRESHOLDS", low, high] => match (low.parse(), high.parse()) {
+            (Ok(low), Ok(high)) => Some(SpefDesignFlow::SLEWTHRESHOLDS(low, high)),
+            _ => None,
+        },
+        ["COUPLING", coupling] => Some(SpefDesignFlow::COUPLING(coupling.to_string())),
+        // a standard keyword with arguments that do not fit it
+        [keyword, ..] if DESIGN_FLOW_KEYWORDS.contains(keyword) || keyword.starts_with("NETLIST_TYPE_") => None,
+        _ => Some(SpefDesignFlow::OTHER(flow_value.to_string())),
+    };
+
+    design_flow.ok_or_else(|| {
+        SpefError::SEMANTIC(pair_error_info(file_name, pair, &format!("Invalid *DESIGN_FLOW value \"{flow_value}\"")))
+    })
+}
+
+/// apply a header entry to the typed header, the grammar already checked how many values each keyword has
+fn apply_header_entry(
+    header: &mut spef_data::SpefHeader,
+    header_entry: &spef_data::SpefHeaderEntry,
+    pair: &Pair<Rule>,
+    file_name: &str,
+) -> Result<(), SpefError> {
+    let header_values = header_entry.get_header_values();
+    let first_value = header_values.first().copied().unwrap_or_default();
+    let first_char = |index: usize| header_values.get(index).and_then(|value| value.chars().next());
+    match header_entry.get_header_key() {
+        "*SPEF" => header.spef_version = first_value.to_string(),
+        "*DESIGN" => header.design_name = first_value.to_string(),
+        "*DATE" => header.date = first_value.to_string(),
+        "*VENDOR" => header.vendor = first_value.to_string(),
+        "*PROGRAM" => header.program = first_value.to_string(),
+        "*VERSION" => header.version = first_value.to_string(),
+        "*DESIGN_FLOW" => {
+            header.design_flow = header_values
+                .iter()
+                .map(|flow_value| process_design_flow(pair, flow_value, file_name))
+                .collect::<Result<Vec<_>, SpefError>>()?;
+        }
+        "*DIVIDER" => header.divider = first_char(0).unwrap_or(header.divider),
+        "*DELIMITER" => header.delimiter = first_char(0).unwrap_or(header.delimiter),
+        "*BUS_DELIMITER" => header.bus_delimiter = (first_char(0).unwrap_or(header.bus_delimiter.0), first_char(1)),
+        "*T_UNIT" => header.time_unit = process_unit(pair, &header_values, file_name, spef_data::SpefQuantity::TIME)?,
+        "*C_UNIT" => {
+            header.cap_unit = process_unit(pair, &header_values, file_name, spef_data::SpefQuantity::CAPACITANCE)?
         }
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-            pair.as_span(),
-        )),
-    }
-}
-
-fn process_conn_entry(pair: Pair<Rule>) -> Result<spef_data::SpefConnEntry, pest::error::Error<Rule>> {
-    let pair_clone = pair.clone();
-    let line_no = pair_clone.line_col().0;
-
-    let mut inner_rules = pair_clone.into_inner();
-
-    let conn_type_pair = inner_rules.next().unwrap();
-    let pin_name_pair = inner_rules.next().unwrap();
-    let conn_dir_pair = inner_rules.next().unwrap();
-    let coordinates_pair = inner_rules.next().unwrap();
-    let load_pair = inner_rules.next().unwrap();
-    let driver_pair = inner_rules.next().unwrap();
-
-    let type_pair_result = process_conn_type_enum(conn_type_pair);
-    let name_pair_result = process_string(pin_name_pair);
-    let dir_pair_result = process_conn_dir_enum(conn_dir_pair);
-    let coor_pair_result = process_coordinates(coordinates_pair);
-    let load_pair_result = process_float(load_pair);
-    let driver_pair_result = process_string(driver_pair);
-
-    match (type_pair_result, name_pair_result, dir_pair_result, coor_pair_result, load_pair_result, driver_pair_result)
-    {
-        (Ok(conn_type), Ok(pin_name), Ok(conn_dir), Ok(coordinates), Ok(load), Ok(driver)) => {
-            let current_conn =
-                spef_data::SpefConnEntry::new("tbd", line_no, conn_type, conn_dir, pin_name, driver, load, coordinates);
-            Ok(current_conn)
+        "*R_UNIT" => {
+            header.res_unit = process_unit(pair, &header_values, file_name, spef_data::SpefQuantity::RESISTANCE)?
         }
-        _ => Err(pest::error::Error::new_from_span(
-            pest::error::ErrorVariant::CustomError { message: "Unknown rule".into() },
-            pair.as_span(),
-        )),
+        "*L_UNIT" => {
+            header.induc_unit = process_unit(pair, &header_values, file_name, spef_data::SpefQuantity::INDUCTANCE)?
+        }
+        _ => return Err(SpefError::SYNTAX(pair_error_info(file_name, pair, "Unknown header keyword"))),
     }
+    Ok(())
 }
 
-pub fn parse_spef_file(spef_file_path: &str) -> Result<spef_data::SpefExchange, pest::error::Error<Rule>> {
-    // !TODO: replace .expect with match or let if
-    let unparsed_file = fs::read_to_string(spef_file_path).expect("cannot read file");
-    let spef_entries = SpefParser::parse(Rule::file, &unparsed_file).expect("unsuccessful parse");
+/// process pest pairs that matches spef rnet entry, creating a SpefReducedNet
+fn process_rnet_entry(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::SpefReducedNet, SpefError> {
+    let line_no = pair.line_col().0;
 
-    let mut exchange_data =
-        spef_data::SpefExchange::new(spef_data::SpefStringValue { value: spef_file_path.to_string() });
+    let mut inner_rules = pair.clone().into_inner();
 
-    let mut current_net: spef_data::SpefNet = spef_data::SpefNet::new(0, "None".to_string(), 0.0);
+    let keyword_pair = next_pair(&mut inner_rules, &pair, file_name, "*R_NET or *R_PNET")?;
+    let name_pair = next_pair(&mut inner_rules, &pair, file_name, "net name")?;
+    let cap_pair = next_pair(&mut inner_rules, &pair, file_name, "net total cap")?;
+
+    let name = process_string(name_pair);
+    let cap = process_par_value(cap_pair, file_name)?;
+
+    let mut reduced_net = spef_data::SpefReducedNet::new(line_no, name, cap);
+    reduced_net.set_physical(keyword_pair.as_str() == "*R_PNET");
+    Ok(reduced_net)
+}
+
+/// process pest pairs that matches the *DRIVER line opening a driver reduction
+fn process_driver_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefDriverReduction, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+    let driver_pair = next_pair(&mut inner_rules, &pair, file_name, "driver pin")?;
+    let driver = process_node_ref(driver_pair, delimiter, net_names);
+
+    Ok(spef_data::SpefDriverReduction::new(file_name, line_no, driver))
+}
 
-    for entry in spef_entries {
+/// process pest pairs that matches *C2_R1_C1 c2 r1 c1
+fn process_pi_model_entry(
+    pair: Pair<Rule>,
+    file_name: &str,
+) -> Result<(spef_data::SpefParValue, spef_data::SpefParValue, spef_data::SpefParValue), SpefError> {
+    let mut inner_rules = pair.clone().into_inner();
+
+    let c2_pair = next_pair(&mut inner_rules, &pair, file_name, "pi model c2")?;
+    let r1_pair = next_pair(&mut inner_rules, &pair, file_name, "pi model r1")?;
+    let c1_pair = next_pair(&mut inner_rules, &pair, file_name, "pi model c1")?;
+
+    Ok((
+        process_par_value(c2_pair, file_name)?,
+        process_par_value(r1_pair, file_name)?,
+        process_par_value(c1_pair, file_name)?,
+    ))
+}
+
+/// process pest pairs that matches *RC pin rc
+fn process_rc_entry(
+    pair: Pair<Rule>,
+    file_name: &Arc<str>,
+    delimiter: char,
+    net_names: &[spef_data::SpefNameRef],
+) -> Result<spef_data::SpefRcDesc, SpefError> {
+    let line_no = pair.line_col().0;
+
+    let mut inner_rules = pair.clone().into_inner();
+
+    let pin_pair = next_pair(&mut inner_rules, &pair, file_name, "load pin")?;
+    let rc_pair = next_pair(&mut inner_rules, &pair, file_name, "load rc")?;
+    let pin = process_node_ref(pin_pair, delimiter, net_names);
+
+    Ok(spef_data::SpefRcDesc::new(file_name, line_no, pin, process_par_value(rc_pair, file_name)?))
+}
+
+/// process one real or ( real imaginary ) number of a complex value
+fn process_complex_number(pair: Pair<Rule>, file_name: &str) -> Result<(f64, f64), SpefError> {
+    match pair.as_rule() {
+        Rule::cnumber => process_coordinates(pair, file_name),
+        _ => Ok((process_float(pair, file_name)?, 0.0)),
+    }
+}
+
+/// process a complex value, either one number or a min:typ:max triplet
+fn process_complex_value(pair: Pair<Rule>, file_name: &str) -> Result<spef_data::SpefComplexValue, SpefError> {
+    let values: Vec<(f64, f64)> = pair
+        .clone()
+        .into_inner()
+        .map(|num_pair| process_complex_number(num_pair, file_name))
+        .collect::<Result<_, _>>()?;
+
+    match values.as_slice() {
+        [(real, imaginary)] => Ok(spef_data::SpefComplexValue {
+            real: spef_data::SpefParValue::SCALAR(*real),
+            imaginary: spef_data::SpefParValue::SCALAR(*imaginary),
+        }),
+        [(min_real, min_imaginary), (typ_real, typ_imaginary), (max_real, max_imaginary)] => {
+            Ok(spef_data::SpefComplexValue {
+                real: spef_data::SpefParValue::TRIPLET(*min_real, *typ_real, *max_real),
+                imaginary: spef_data::SpefParValue::TRIPLET(*min_imaginary, *typ_imaginary, *max_imaginary),
+            })
+        }
+        _ => Err(SpefError::SYNTAX(pair_error_info(file_name, &pair, "Failed to parse complex value"))),
+    }
+}
+
+/// process *Q count poles... or *K count residues..., the count has to match the values that follow
+fn process_pole_residue_entry(
+    pair: Pair<Rule>,
+    file_name: &str,
+) -> Result<Vec<spef_data::SpefComplexValue>, SpefError> {
+    let mut inner_rules = pair.clone().into_inner();
+
+    let count_pair = next_pair(&mut inner_rules, &pair, file_name, "pole/residue count")?;
+    let count = process_index(count_pair, file_name)?;
+
+    let values: Vec<spef_data::SpefComplexValue> =
+        inner_rules.map(|value_pair| process_complex_value(value_pair, file_name)).collect::<Result<_, _>>()?;
+    if values.len() != count {
+        let message = format!("Expected {} values, found {}", count, values.len());
+        return Err(SpefError::SEMANTIC(pair_error_info(file_name, &pair, &message)));
+    }
+    Ok(values)
+}
+
+/// Optional parts of a spef file the parser reads on request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpefParseOptions {
+    /// keep the layer and bounding box of `// $llx=.. $lly=.. $urx=.. $ury=.. $lvl=..` comments
+    /// on conn, cap and res lines
+    pub vendor_annotations: bool,
+}
+
+/// spef parser state machine, the section it is in decides which entries are legal
+struct SpefParserState {
+    file_name: Arc<str>,
+    options: SpefParseOptions,
+    current_section: spef_data::SectionType,
+    current_net: Option<spef_data::SpefNet>,
+    current_reduced_net: Option<spef_data::SpefReducedNet>,
+    exchange_data: spef_data::SpefExchange,
+}
+
+impl SpefParserState {
+    fn new(file_name: &str, options: SpefParseOptions, exchange_data: spef_data::SpefExchange) -> SpefParserState {
+        SpefParserState {
+            file_name: Arc::from(file_name),
+            options,
+            current_section: spef_data::SectionType::HEADER,
+            current_net: None,
+            current_reduced_net: None,
+            exchange_data,
+        }
+    }
+
+    /// error for a section or an entry that shows up where the spef section order does not allow it
+    fn section_order_error(&self, pair: &Pair<Rule>, message: &str) -> SpefError {
+        SpefError::SEMANTIC(pair_error_info(&self.file_name, pair, message))
+    }
+
+    /// move to the next section, rejecting sections that are out of order
+    fn enter_section(&mut self, section_type: spef_data::SectionType, pair: &Pair<Rule>) -> Result<(), SpefError> {
+        if !section_type.can_follow(&self.current_section) {
+            let message = format!("{} is not allowed after {}", section_type.keyword(), self.current_section.keyword());
+            return Err(self.section_order_error(pair, &message));
+        }
+        self.current_section = section_type;
+        Ok(())
+    }
+
+    /// check an entry is in one of the sections it belongs to
+    fn expect_section(
+        &self,
+        entry_name: &str,
+        allowed_sections: &[spef_data::SectionType],
+        pair: &Pair<Rule>,
+    ) -> Result<(), SpefError> {
+        if allowed_sections.contains(&self.current_section) {
+            return Ok(());
+        }
+        let message = format!("{} entry is not allowed in {} section", entry_name, self.current_section.keyword());
+        Err(self.section_order_error(pair, &message))
+    }
+
+    /// pin delimiter of the file and the names of the open net, to tell the nodes of a line apart.
+    /// A net opened as *1 may name its nodes by its mapped name and the other way round.
+    fn node_context(&self) -> (char, Vec<spef_data::SpefNameRef>) {
+        let net_name = match (&self.current_net, &self.current_reduced_net) {
+            (Some(net), _) => net.name.as_str(),
+            (None, Some(reduced_net)) => reduced_net.name.as_str(),
+            (None, None) => "",
+        };
+        (self.exchange_data.get_header().delimiter, self.net_names(net_name))
+    }
+
+    /// a net by the name it is written with and by its name map alias
+    fn net_names(&self, net_name: &str) -> Vec<spef_data::SpefNameRef> {
+        let mut net_names = vec![spef_data::SpefNameRef::from_spef_name(net_name)];
+        match &net_names[0] {
+            spef_data::SpefNameRef::INDEX(index) => {
+                if let Some(name) = self.exchange_data.get_name_by_index(*index) {
+                    net_names.push(spef_data::SpefNameRef::NAME(name.to_string()));
+                }
+            }
+            spef_data::SpefNameRef::NAME(name) => {
+                if let Some(index) = self.exchange_data.get_index_by_name(name) {
+                    net_names.push(spef_data::SpefNameRef::INDEX(index));
+                }
+            }
+        }
+        net_names
+    }
+
+    /// every name map index an entry refers to has to be in the *NAME_MAP section
+    fn check_names<I: IntoIterator<Item = spef_data::SpefNameRef>>(
+        &self,
+        names: I,
+        pair: &Pair<Rule>,
+    ) -> Result<(), SpefError> {
+        for name in names {
+            if let spef_data::SpefNameRef::INDEX(index) = name {
+                if self.exchange_data.get_name_by_index(index).is_none() {
+                    let message = format!("*{} is not in the name map", index);
+                    return Err(SpefError::SEMANTIC(pair_error_info(&self.file_name, pair, &message)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// check the names of spef names such as *1 or VDD
+    fn check_spef_names<'a, I: IntoIterator<Item = &'a String>>(
+        &self,
+        names: I,
+        pair: &Pair<Rule>,
+    ) -> Result<(), SpefError> {
+        self.check_names(names.into_iter().map(|name| spef_data::SpefNameRef::from_spef_name(name)), pair)
+    }
+
+    /// check the nets, instances and ports of nodes
+    fn check_node_names<'a, I: IntoIterator<Item = &'a spef_data::SpefNodeRef>>(
+        &self,
+        nodes: I,
+        pair: &Pair<Rule>,
+    ) -> Result<(), SpefError> {
+        self.check_names(nodes.into_iter().map(|node| node.get_owner().clone()), pair)
+    }
+
+    /// layer and bounding box of the line when vendor annotations are asked for
+    fn vendor_geometry(&self, pair: &Pair<Rule>) -> Result<Option<spef_data::SpefVendorGeometry>, SpefError> {
+        if !self.options.vendor_annotations {
+            return Ok(None);
+        }
+        process_vendor_annotation(pair, &self.file_name)
+    }
+
+    /// a *DRIVER block needs its *CELL and *C2_R1_C1 lines before *LOADS
+    fn check_driver_complete(&self, pair: &Pair<Rule>) -> Result<(), SpefError> {
+        let driver = self.current_reduced_net.as_ref().and_then(|reduced_net| reduced_net.get_drivers().last());
+        let delimiter = self.exchange_data.get_header().delimiter;
+        match driver {
+            Some(driver) if driver.get_cell().is_none() => {
+                let driver_name = driver.get_driver().to_spef_name(delimiter);
+                Err(self.section_order_error(pair, &format!("*DRIVER {} has no *CELL", driver_name)))
+            }
+            Some(driver) if driver.get_pi_model().is_none() => {
+                let driver_name = driver.get_driver().to_spef_name(delimiter);
+                Err(self.section_order_error(pair, &format!("*DRIVER {} has no *C2_R1_C1", driver_name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// process the lines of a *R_NET block
+    fn process_reduced_net_entry(&mut self, entry: Pair<Rule>) -> Result<(), SpefError> {
+        use spef_data::SectionType;
+
+        let file_name = &self.file_name;
         match entry.as_rule() {
-            Rule::header_entry => {
-                let parse_result = process_header_entry(entry.clone());
-                match parse_result {
-                    Ok(result) => {
-                        exchange_data.add_header_entry(result.clone());
-                        result
+            Rule::rnet_entry => {
+                let reduced_net = process_rnet_entry(entry.clone(), file_name)?;
+                self.check_spef_names([&reduced_net.name], &entry)?;
+                self.enter_section(SectionType::RNET, &entry)?;
+                self.current_reduced_net = Some(reduced_net);
+            }
+            Rule::driver_entry => {
+                let (delimiter, net_names) = self.node_context();
+                let driver = process_driver_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                self.check_node_names([driver.get_driver()], &entry)?;
+                self.enter_section(SectionType::DRIVER, &entry)?;
+                if let Some(reduced_net) = self.current_reduced_net.as_mut() {
+                    reduced_net.add_driver(driver);
+                }
+            }
+            Rule::cell_entry => {
+                self.expect_section("*CELL", &[SectionType::DRIVER], &entry)?;
+                let cell_pair = next_pair(&mut entry.clone().into_inner(), &entry, file_name, "cell")?;
+                let driver = self.current_reduced_net.as_mut().and_then(|net| net.get_last_driver_mut());
+                match driver {
+                    Some(driver) if driver.get_cell().is_none() => driver.set_cell(process_string(cell_pair)),
+                    _ => return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, "Duplicated *CELL"))),
+                }
+            }
+            Rule::pi_model_entry => {
+                self.expect_section("*C2_R1_C1", &[SectionType::DRIVER], &entry)?;
+                let pi_model = process_pi_model_entry(entry.clone(), file_name)?;
+                let driver = self.current_reduced_net.as_mut().and_then(|net| net.get_last_driver_mut());
+                match driver {
+                    Some(driver) if driver.get_pi_model().is_none() => driver.set_pi_model(pi_model),
+                    _ => return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, "Duplicated *C2_R1_C1"))),
+                }
+            }
+            Rule::rc_entry => {
+                self.expect_section("*RC", &[SectionType::LOADS], &entry)?;
+                let (delimiter, net_names) = self.node_context();
+                let rc_desc = process_rc_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                self.check_node_names([rc_desc.get_pin()], &entry)?;
+                if let Some(driver) = self.current_reduced_net.as_mut().and_then(|net| net.get_last_driver_mut()) {
+                    driver.add_load(rc_desc);
+                }
+            }
+            Rule::pole_entry => {
+                self.expect_section("*Q", &[SectionType::LOADS], &entry)?;
+                let poles = process_pole_residue_entry(entry.clone(), file_name)?;
+                let load = self
+                    .current_reduced_net
+                    .as_mut()
+                    .and_then(|net| net.get_last_driver_mut())
+                    .and_then(|driver| driver.get_last_load_mut());
+                match load {
+                    Some(load) if load.get_poles().is_empty() => load.set_poles(poles),
+                    _ => {
+                        return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, "*Q has to follow an *RC")))
+                    }
+                }
+            }
+            Rule::residue_entry => {
+                self.expect_section("*K", &[SectionType::LOADS], &entry)?;
+                let residues = process_pole_residue_entry(entry.clone(), file_name)?;
+                let load = self
+                    .current_reduced_net
+                    .as_mut()
+                    .and_then(|net| net.get_last_driver_mut())
+                    .and_then(|driver| driver.get_last_load_mut());
+                match load {
+                    Some(load) if load.get_residues().is_empty() && load.get_poles().len() == residues.len() => {
+                        load.set_residues(residues)
                     }
-                    Err(err) => return Err(err.clone()),
-                };
+                    _ => {
+                        let message = "*K has to follow a *Q with as many poles";
+                        return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, message)));
+                    }
+                }
             }
-            Rule::name_map_entry => {
-                let parse_result = process_namemap_entry(entry.clone());
-                match parse_result {
-                    Ok(result) => {
-                        exchange_data.add_namemap_entry(result.clone());
-                        result
+            _ => return Err(SpefError::SYNTAX(pair_error_info(file_name, &entry, "Unexpected entry"))),
+        }
+        Ok(())
+    }
+
+    fn process_entry(&mut self, entry: Pair<Rule>) -> Result<(), SpefError> {
+        use spef_data::SectionType;
+
+        let file_name = &self.file_name;
+        match entry.as_rule() {
+            Rule::section => {
+                let section_entry = process_section_entry(entry.clone(), file_name)?;
+                self.enter_section(section_entry.get_section_type().clone(), &entry)?;
+                if self.current_section == SectionType::END {
+                    if let Some(net) = self.current_net.take() {
+                        self.exchange_data.add_net(net);
+                    }
+                    if let Some(reduced_net) = self.current_reduced_net.take() {
+                        self.exchange_data.add_reduced_net(reduced_net);
                     }
-                    Err(err) => return Err(err.clone()),
-                };
+                }
+                if self.current_section == SectionType::LOADS {
+                    self.check_driver_complete(&entry)?;
+                }
             }
-            Rule::ports_entry => {
-                let parse_result = process_port_entry(entry.clone());
-                match parse_result {
-                    Ok(result) => {
-                        exchange_data.add_port_entry(result.clone());
-                        result
+            Rule::header_entry => {
+                self.expect_section("header", &[SectionType::HEADER], &entry)?;
+                let header_entry = process_header_entry(entry.clone(), file_name)?;
+                apply_header_entry(self.exchange_data.get_header_mut(), &header_entry, &entry, file_name)?;
+            }
+            Rule::name_map_entry if is_name_map_line(&entry, &self.current_section) => {
+                self.expect_section("*NAME_MAP", &[SectionType::NAMEMAP], &entry)?;
+                let namemap_entry = process_namemap_entry(entry.clone(), file_name)?;
+                if self.exchange_data.get_name_by_index(namemap_entry.get_index()).is_some() {
+                    let message = format!("Name map index *{} is already defined", namemap_entry.get_index());
+                    return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, &message)));
+                }
+                if let Some(index) = self.exchange_data.get_index_by_name(namemap_entry.get_name()) {
+                    let message = format!("Name {} is already mapped to *{}", namemap_entry.get_name(), index);
+                    return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, &message)));
+                }
+                self.exchange_data.add_namemap_entry(namemap_entry);
+            }
+            Rule::power_nets_entry | Rule::ground_nets_entry => {
+                self.enter_section(SectionType::POWER, &entry)?;
+                let is_power = entry.as_rule() == Rule::power_nets_entry;
+                let net_names = process_supply_nets_entry(entry.clone());
+                self.check_spef_names(&net_names, &entry)?;
+                for net_name in net_names {
+                    if is_power {
+                        self.exchange_data.add_power_net(net_name);
+                    } else {
+                        self.exchange_data.add_ground_net(net_name);
                     }
-                    Err(err) => return Err(err.clone()),
-                };
+                }
+            }
+            Rule::define_entry => {
+                let define_entry = process_define_entry(entry.clone(), file_name)?;
+                if let Some(instance) = define_entry
+                    .get_instances()
+                    .iter()
+                    .find(|instance| self.exchange_data.get_instance_entity(instance).is_some())
+                {
+                    let message = format!("Instance {} is already defined", instance);
+                    return Err(SpefError::SEMANTIC(pair_error_info(file_name, &entry, &message)));
+                }
+                self.check_spef_names(define_entry.get_instances(), &entry)?;
+                self.enter_section(SectionType::DEFINE, &entry)?;
+                self.exchange_data.add_define_entry(define_entry);
+            }
+            Rule::ports_entry | Rule::name_map_entry => {
+                self.expect_section("*PORTS", &[SectionType::PORTS, SectionType::PHYSICALPORTS], &entry)?;
+                let mut port_entry = process_port_entry(entry.clone(), file_name)?;
+                self.check_spef_names([&port_entry.get_name().to_string()], &entry)?;
+                port_entry.set_resolved_name(self.exchange_data.resolve_name(port_entry.get_name()));
+                port_entry.set_physical(self.current_section == SectionType::PHYSICALPORTS);
+                self.exchange_data.add_port_entry(port_entry);
             }
             Rule::dnet_entry => {
-                let parse_result = process_dnet_entry(entry, &mut current_net);
-                match parse_result {
-                    Ok(result) => result,
-                    Err(err) => return Err(err.clone()),
-                };
+                let net = process_dnet_entry(entry.clone(), file_name)?;
+                self.check_spef_names([&net.name], &entry)?;
+                self.enter_section(SectionType::DNET, &entry)?;
+                self.current_net = Some(net);
             }
             Rule::conn_entry => {
-                let parse_result = process_conn_entry(entry);
-                match parse_result {
-                    Ok(result) => {
-                        current_net.add_connection(&result);
-                        result
+                self.expect_section("*CONN", &[SectionType::CONN], &entry)?;
+                let (delimiter, net_names) = self.node_context();
+                let mut conn_entry = process_conn_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                self.check_node_names([conn_entry.get_node()], &entry)?;
+                if let Some(geometry) = self.vendor_geometry(&entry)? {
+                    conn_entry.set_vendor_geometry(&geometry);
+                }
+                if let Some(net) = self.current_net.as_mut() {
+                    net.add_connection(conn_entry);
+                }
+            }
+            Rule::internal_node_entry => {
+                self.expect_section("*N", &[SectionType::CONN], &entry)?;
+                let (delimiter, net_names) = self.node_context();
+                let internal_node = process_internal_node_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                self.check_node_names([internal_node.get_node()], &entry)?;
+                if let Some(net) = self.current_net.as_mut() {
+                    net.add_internal_node(internal_node);
+                }
+            }
+            Rule::element_entry => {
+                let allowed_sections = [SectionType::CAP, SectionType::RES, SectionType::INDUC];
+                self.expect_section("*CAP, *RES or *INDUC", &allowed_sections, &entry)?;
+                let (delimiter, net_names) = self.node_context();
+                match self.current_section {
+                    SectionType::CAP => {
+                        let mut cap_entry = process_cap_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                        self.check_node_names(
+                            std::iter::once(cap_entry.get_node1()).chain(cap_entry.get_node2()),
+                            &entry,
+                        )?;
+                        if let Some(geometry) = self.vendor_geometry(&entry)? {
+                            cap_entry.set_geometry(geometry);
+                        }
+                        if let Some(net) = self.current_net.as_mut() {
+                            net.add_cap(cap_entry);
+                        }
+                    }
+                    SectionType::RES => {
+                        let mut res_entry = process_res_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                        self.check_node_names([res_entry.get_node1(), res_entry.get_node2()], &entry)?;
+                        if let Some(geometry) = self.vendor_geometry(&entry)? {
+                            res_entry.set_geometry(geometry);
+                        }
+                        if let Some(net) = self.current_net.as_mut() {
+                            net.add_res(res_entry);
+                        }
+                    }
+                    _ => {
+                        let induc_entry = process_induc_entry(entry.clone(), file_name, delimiter, &net_names)?;
+                        self.check_node_names([induc_entry.get_node1(), induc_entry.get_node2()], &entry)?;
+                        if let Some(net) = self.current_net.as_mut() {
+                            net.add_induc(induc_entry);
+                        }
                     }
-                    Err(err) => return Err(err.clone()),
-                };
+                }
+            }
+            Rule::rnet_entry
+            | Rule::driver_entry
+            | Rule::cell_entry
+            | Rule::pi_model_entry
+            | Rule::rc_entry
+            | Rule::pole_entry
+            | Rule::residue_entry => self.process_reduced_net_entry(entry)?,
+            Rule::EOI => {
+                if let Some(net) = self.current_net.as_ref() {
+                    let keyword = if net.is_physical() { "*D_PNET" } else { "*D_NET" };
+                    let message = format!("{} {} is not closed by *END", keyword, net.name);
+                    return Err(self.section_order_error(&entry, &message));
+                }
+                if let Some(reduced_net) = self.current_reduced_net.as_ref() {
+                    let keyword = if reduced_net.is_physical() { "*R_PNET" } else { "*R_NET" };
+                    let message = format!("{} {} is not closed by *END", keyword, reduced_net.name);
+                    return Err(self.section_order_error(&entry, &message));
+                }
             }
-            Rule::cap_entry => {}
-            Rule::res_entry => {}
-            Rule::EOI => (),
-            _ => unreachable!(),
+            _ => return Err(SpefError::SYNTAX(pair_error_info(file_name, &entry, "Unexpected entry"))),
         }
+        Ok(())
     }
-    Ok(exchange_data)
+}
+
+pub fn parse_spef_file(spef_file_path: &str) -> Result<spef_data::SpefExchange, SpefError> {
+    parse_spef_file_with_options(spef_file_path, SpefParseOptions::default())
+}
+
+/// parse a spef file, reading the optional parts the options ask for
+pub fn parse_spef_file_with_options(
+    spef_file_path: &str,
+    options: SpefParseOptions,
+) -> Result<spef_data::SpefExchange, SpefError> {
+    // the stream reader takes the *D_NET blocks, the bulk of a file, through the hand-written net parser
+    spef_stream::SpefStreamReader::open(spef_file_path, options)?.read_all()
 }
@@ -1,4 +1,9 @@
-use std::fmt::Debug;
+#![allow(clippy::upper_case_acronyms)]
+
+use super::spef_error::{SpefError, SpefErrorInfo};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
 
 pub trait SpefValue: Debug {
     fn is_string(&self) -> bool {
@@ -64,6 +69,90 @@ impl SpefValue for SpefStringValue {
     }
 }
 
+/// spef parasitic value, either one number or a min:typ:max triplet.
+/// # Examples
+/// 0.15
+/// 0.12:0.15:0.19
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefParValue {
+    SCALAR(f64),
+    TRIPLET(f64, f64, f64),
+}
+
+/// process corner picked out of a min:typ:max triplet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefCorner {
+    MIN,
+    TYP,
+    MAX,
+}
+
+impl SpefParValue {
+    pub fn is_triplet(&self) -> bool {
+        matches!(self, SpefParValue::TRIPLET(..))
+    }
+
+    /// the value at a corner, a scalar is the same at every corner
+    pub fn get_corner_value(&self, corner: SpefCorner) -> f64 {
+        match (self, corner) {
+            (SpefParValue::SCALAR(value), _) => *value,
+            (SpefParValue::TRIPLET(min, _, _), SpefCorner::MIN) => *min,
+            (SpefParValue::TRIPLET(_, typ, _), SpefCorner::TYP) => *typ,
+            (SpefParValue::TRIPLET(_, _, max), SpefCorner::MAX) => *max,
+        }
+    }
+
+    /// collapse the value onto one corner
+    pub fn project(&self, corner: SpefCorner) -> SpefParValue {
+        SpefParValue::SCALAR(self.get_corner_value(corner))
+    }
+
+    /// multiply every corner by the same factor
+    pub fn scale(&self, factor: f64) -> SpefParValue {
+        match self {
+            SpefParValue::SCALAR(value) => SpefParValue::SCALAR(value * factor),
+            SpefParValue::TRIPLET(min, typ, max) => SpefParValue::TRIPLET(min * factor, typ * factor, max * factor),
+        }
+    }
+}
+
+impl Default for SpefParValue {
+    fn default() -> SpefParValue {
+        SpefParValue::SCALAR(0.0)
+    }
+}
+
+impl SpefValue for SpefParValue {
+    fn is_float(&self) -> bool {
+        true
+    }
+
+    fn get_float_value(&self) -> f64 {
+        self.get_corner_value(SpefCorner::TYP)
+    }
+}
+
+/// spef complex value of a pole or a residue, a real number has a zero imaginary part.
+/// # Examples
+/// ( 1.2 -0.3 )
+/// ( 1.1 -0.2 ):( 1.2 -0.3 ):( 1.3 -0.4 )
+/// 0.5
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpefComplexValue {
+    pub real: SpefParValue,
+    pub imaginary: SpefParValue,
+}
+
+impl SpefComplexValue {
+    pub fn project(&self, corner: SpefCorner) -> SpefComplexValue {
+        SpefComplexValue { real: self.real.project(corner), imaginary: self.imaginary.project(corner) }
+    }
+
+    pub fn scale(&self, factor: f64) -> SpefComplexValue {
+        SpefComplexValue { real: self.real.scale(factor), imaginary: self.imaginary.scale(factor) }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SpefCoordinatesValue {
     pub(crate) value: (f64, f64),
@@ -104,41 +193,114 @@ pub trait SpefEntryTrait: Debug {
     fn is_res_entry(&self) -> bool {
         false
     }
+    fn is_induc_entry(&self) -> bool {
+        false
+    }
     fn as_any(&self) -> &dyn std::any::Any;
 }
 
 /// spef line entry basic info and it's methods
 #[derive(Clone, Debug)]
 pub struct SpefEntryBasicInfo {
-    file_name: SpefStringValue,
+    // shared by every entry of the file
+    file_name: Arc<str>,
     line_no: usize,
 }
 
 impl SpefEntryBasicInfo {
-    fn new(file_name: &str, line_no: usize) -> SpefEntryBasicInfo {
-        SpefEntryBasicInfo { file_name: SpefStringValue { value: file_name.to_string() }, line_no: line_no }
+    fn new(file_name: &Arc<str>, line_no: usize) -> SpefEntryBasicInfo {
+        SpefEntryBasicInfo { file_name: Arc::clone(file_name), line_no }
     }
 
     pub fn get_file_name(&self) -> &str {
-        &self.file_name.get_str_value()
+        &self.file_name
     }
 
     pub fn get_line_no(&self) -> usize {
         self.line_no
     }
+
+    fn shift_line_no(&mut self, line_offset: usize) {
+        self.line_no += line_offset;
+    }
 }
 
-#[derive(Clone, Debug)]
+/// Sections of a spef file, in the order the parser walks through them:
+/// HEADER -> NAMEMAP -> POWER -> PORTS -> PHYSICALPORTS -> DEFINE
+///     -> (DNET -> CONN -> CAP -> RES -> INDUC -> END | RNET -> (DRIVER -> LOADS)* -> END)*
+/// NAMEMAP, POWER, PORTS, PHYSICALPORTS, DEFINE, CONN, CAP, RES and INDUC may be skipped.
+/// POWER holds the *POWER_NETS and *GROUND_NETS lines, DEFINE holds the *DEFINE and *PDEFINE lines.
+/// *D_PNET and *R_PNET physical nets walk through the same sections as *D_NET and *R_NET.
+/// DNET, RNET and DRIVER have no section marker, they are opened by their *D_NET, *R_NET and *DRIVER lines.
+#[derive(Clone, Debug, PartialEq)]
 pub enum SectionType {
     HEADER,
     PORTS,
+    PHYSICALPORTS,
     NAMEMAP,
+    POWER,
+    DEFINE,
+    DNET,
     CONN,
     CAP,
     RES,
+    INDUC,
+    RNET,
+    DRIVER,
+    LOADS,
     END
 }
 
+impl SectionType {
+    /// the keyword that opens the section in a spef file
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SectionType::HEADER => "header",
+            SectionType::PORTS => "*PORTS",
+            SectionType::PHYSICALPORTS => "*PHYSICAL_PORTS",
+            SectionType::NAMEMAP => "*NAME_MAP",
+            SectionType::POWER => "*POWER_NETS/*GROUND_NETS",
+            SectionType::DEFINE => "*DEFINE",
+            SectionType::DNET => "*D_NET",
+            SectionType::CONN => "*CONN",
+            SectionType::CAP => "*CAP",
+            SectionType::RES => "*RES",
+            SectionType::INDUC => "*INDUC",
+            SectionType::RNET => "*R_NET",
+            SectionType::DRIVER => "*DRIVER",
+            SectionType::LOADS => "*LOADS",
+            SectionType::END => "*END",
+        }
+    }
+
+    /// whether this section may directly follow the previous one
+    pub fn can_follow(&self, previous: &SectionType) -> bool {
+        use SectionType::*;
+        match self {
+            HEADER => false,
+            NAMEMAP => matches!(previous, HEADER),
+            POWER => matches!(previous, HEADER | NAMEMAP | POWER),
+            PORTS => matches!(previous, HEADER | NAMEMAP | POWER),
+            PHYSICALPORTS => matches!(previous, HEADER | NAMEMAP | POWER | PORTS),
+            DEFINE => matches!(previous, HEADER | NAMEMAP | POWER | PORTS | PHYSICALPORTS | DEFINE),
+            DNET | RNET => matches!(previous, HEADER | NAMEMAP | POWER | PORTS | PHYSICALPORTS | DEFINE | END),
+            CONN => matches!(previous, DNET),
+            CAP => matches!(previous, DNET | CONN),
+            RES => matches!(previous, DNET | CONN | CAP),
+            INDUC => matches!(previous, DNET | CONN | CAP | RES),
+            DRIVER => matches!(previous, RNET | LOADS),
+            LOADS => matches!(previous, DRIVER),
+            END => matches!(previous, DNET | CONN | CAP | RES | INDUC | RNET | LOADS),
+        }
+    }
+
+    /// whether the parser is inside a *D_NET/*R_NET ... *END block
+    pub fn is_net_section(&self) -> bool {
+        use SectionType::*;
+        matches!(self, DNET | CONN | CAP | RES | INDUC | RNET | DRIVER | LOADS)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SpefSectionEntry {
     basic_info: SpefEntryBasicInfo,
@@ -146,7 +308,7 @@ pub struct SpefSectionEntry {
 }
 
 impl SpefSectionEntry {
-    pub fn new(file_name: &str, line_no: usize, section_type: SectionType) -> SpefSectionEntry {
+    pub fn new(file_name: &Arc<str>, line_no: usize, section_type: SectionType) -> SpefSectionEntry {
         SpefSectionEntry { basic_info: SpefEntryBasicInfo::new(file_name, line_no), section_type }
     }
 
@@ -172,15 +334,15 @@ impl SpefEntryTrait for SpefSectionEntry {
 pub struct SpefHeaderEntry {
     basic_info: SpefEntryBasicInfo,
     header_key: SpefStringValue,
-    header_value: SpefStringValue,
+    header_values: Vec<SpefStringValue>,
 }
 
 impl SpefHeaderEntry {
-    pub fn new(file_name: &str, line_no: usize, header_key: String, header_value: String) -> SpefHeaderEntry {
+    pub fn new(file_name: &Arc<str>, line_no: usize, header_key: String, header_values: Vec<String>) -> SpefHeaderEntry {
         SpefHeaderEntry { 
             basic_info: SpefEntryBasicInfo::new(file_name, line_no), 
             header_key: SpefStringValue { value: header_key }, 
-            header_value: SpefStringValue { value: header_value } 
+            header_values: header_values.into_iter().map(|value| SpefStringValue { value }).collect(),
         }
     }
 
@@ -189,11 +351,12 @@ impl SpefHeaderEntry {
     }
 
     pub fn get_header_key(&self) -> &str {
-        &self.header_key.get_str_value()
+        self.header_key.get_str_value()
     }
-    
-    pub fn get_header_value(&self) -> &str {
-        &self.header_value.get_str_value()
+
+    /// header values without quotes, *DESIGN_FLOW has one value per quoted string
+    pub fn get_header_values(&self) -> Vec<&str> {
+        self.header_values.iter().map(|value| value.get_str_value()).collect()
     }
 }
 
@@ -214,12 +377,13 @@ impl SpefEntryTrait for SpefHeaderEntry {
 pub struct SpefNameMapEntry {
     basic_info: SpefEntryBasicInfo,
     index: usize,
-    name: String,
+    // shared with the name lookup of the exchange
+    name: Arc<str>,
 }
 
 impl SpefNameMapEntry {
-    pub fn new(file_name: &str, line_no: usize, index: usize, name: &str) -> SpefNameMapEntry {
-        SpefNameMapEntry { basic_info: SpefEntryBasicInfo::new(file_name, line_no), index, name: name.to_string() }
+    pub fn new(file_name: &Arc<str>, line_no: usize, index: usize, name: &str) -> SpefNameMapEntry {
+        SpefNameMapEntry { basic_info: SpefEntryBasicInfo::new(file_name, line_no), index, name: Arc::from(name) }
     }
 
     pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
@@ -231,7 +395,7 @@ impl SpefNameMapEntry {
     }
     
     pub fn get_name(&self) -> &str {
-        &self.name.as_str()
+        &self.name
     }
 }
 
@@ -244,11 +408,455 @@ impl SpefEntryTrait for SpefNameMapEntry {
     }
 }
 
+/// Unit names allowed by *T_UNIT, *C_UNIT, *R_UNIT and *L_UNIT
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefUnitName {
+    NS,
+    PS,
+    PF,
+    FF,
+    OHM,
+    KOHM,
+    HENRY,
+    MH,
+    UH,
+}
+
+/// Physical quantity a unit measures
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefQuantity {
+    TIME,
+    CAPACITANCE,
+    RESISTANCE,
+    INDUCTANCE,
+}
+
+impl SpefUnitName {
+    pub fn from_keyword(keyword: &str) -> Option<SpefUnitName> {
+        match keyword {
+            "NS" => Some(SpefUnitName::NS),
+            "PS" => Some(SpefUnitName::PS),
+            "PF" => Some(SpefUnitName::PF),
+            "FF" => Some(SpefUnitName::FF),
+            "OHM" => Some(SpefUnitName::OHM),
+            "KOHM" => Some(SpefUnitName::KOHM),
+            "HENRY" => Some(SpefUnitName::HENRY),
+            "MH" => Some(SpefUnitName::MH),
+            "UH" => Some(SpefUnitName::UH),
+            _ => None,
+        }
+    }
+
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            SpefUnitName::NS => "NS",
+            SpefUnitName::PS => "PS",
+            SpefUnitName::PF => "PF",
+            SpefUnitName::FF => "FF",
+            SpefUnitName::OHM => "OHM",
+            SpefUnitName::KOHM => "KOHM",
+            SpefUnitName::HENRY => "HENRY",
+            SpefUnitName::MH => "MH",
+            SpefUnitName::UH => "UH",
+        }
+    }
+
+    pub fn quantity(&self) -> SpefQuantity {
+        match self {
+            SpefUnitName::NS | SpefUnitName::PS => SpefQuantity::TIME,
+            SpefUnitName::PF | SpefUnitName::FF => SpefQuantity::CAPACITANCE,
+            SpefUnitName::OHM | SpefUnitName::KOHM => SpefQuantity::RESISTANCE,
+            SpefUnitName::HENRY | SpefUnitName::MH | SpefUnitName::UH => SpefQuantity::INDUCTANCE,
+        }
+    }
+
+    /// seconds, farads, ohms or henries in one of this unit
+    pub fn si_scale(&self) -> f64 {
+        match self {
+            SpefUnitName::NS => 1e-9,
+            SpefUnitName::PS => 1e-12,
+            SpefUnitName::PF => 1e-12,
+            SpefUnitName::FF => 1e-15,
+            SpefUnitName::OHM => 1.0,
+            SpefUnitName::KOHM => 1e3,
+            SpefUnitName::HENRY => 1.0,
+            SpefUnitName::MH => 1e-3,
+            SpefUnitName::UH => 1e-6,
+        }
+    }
+}
+
+/// spef unit, a multiplier and a unit name.
+/// # Examples
+/// *C_UNIT 1 PF
+/// *T_UNIT 10 PS
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpefUnit {
+    pub multiplier: f64,
+    pub name: SpefUnitName,
+}
+
+impl SpefUnit {
+    pub fn new(multiplier: f64, name: SpefUnitName) -> SpefUnit {
+        SpefUnit { multiplier, name }
+    }
+
+    /// seconds, farads, ohms or henries in one value written in this unit
+    pub fn si_scale(&self) -> f64 {
+        self.multiplier * self.name.si_scale()
+    }
+
+    /// convert a value written in this unit to SI
+    pub fn to_si(&self, value: SpefParValue) -> SpefParValue {
+        value.scale(self.si_scale())
+    }
+}
+
+/// Pin capacitances included in the net total caps, set by the PIN_CAP design flow
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefPinCap {
+    NONE,
+    INPUTOUTPUT,
+    INPUTONLY,
+}
+
+/// Whether names are relative to the design or to the top of the hierarchy, set by the NAME_SCOPE design flow
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefNameScope {
+    LOCAL,
+    FLAT,
+}
+
+/// Netlist the spef was extracted against, set by the NETLIST_TYPE_* design flows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpefNetlistType {
+    VERILOG,
+    VHDL87,
+    VHDL93,
+    EDIF,
+}
+
+/// One value of *DESIGN_FLOW.
+/// # Examples
+/// *DESIGN_FLOW "COUPLING C" "PIN_CAP NONE" "NAME_SCOPE LOCAL"
+/// COUPLING: how the tool handled coupling caps, the standard leaves the values to the tools
+/// SLEW_THRESHOLDS: low and high thresholds of the slews, in percent of the supply
+/// OTHER: a vendor flow value the standard does not define
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpefDesignFlow {
+    EXTERNALLOADS,
+    EXTERNALSLEWS,
+    FULLCONNECTIVITY,
+    MISSINGNETS,
+    NETLISTTYPE(SpefNetlistType),
+    ROUTINGCONFIDENCE(u32),
+    ADDITIONALLOAD,
+    NAMESCOPE(SpefNameScope),
+    PINCAP(SpefPinCap),
+    SLEWTHRESHOLDS(f64, f64),
+    COUPLING(String),
+    OTHER(String),
+}
+
+/// Name split by the divider and bus delimiter of the file.
+/// Segments keep their escapes, so escaped dividers and bus delimiters stay inside a segment.
+/// # Examples
+/// u1/u2/data\[0\][3] with *DIVIDER / and *BUS_DELIMITER []
+/// segments: u1, u2, data\[0\]
+/// bus_index: 3
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpefHierName {
+    pub segments: Vec<String>,
+    pub bus_index: Option<usize>,
+}
+
+impl SpefHierName {
+    /// instance path of the name, every segment but the last
+    pub fn get_path(&self) -> &[String] {
+        self.segments.split_last().map(|(_, path)| path).unwrap_or_default()
+    }
+
+    /// last segment of the name without its bus index
+    pub fn get_leaf(&self) -> &str {
+        self.segments.last().map(String::as_str).unwrap_or_default()
+    }
+}
+
+/// identifier without its escapes, net\.1 becomes net.1
+pub fn unescape_identifier(name: &str) -> String {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Typed spef header.
+/// Values in the exchange are written in these units:
+/// time_unit: *RC delays of reduced nets and *S slews
+/// cap_unit: net total caps, *CAP values, *L loads and the c2/c1 of *C2_R1_C1
+/// res_unit: *RES values and the r1 of *C2_R1_C1
+/// induc_unit: *INDUC values
+#[derive(Clone, Debug)]
+pub struct SpefHeader {
+    pub spef_version: String,
+    pub design_name: String,
+    pub date: String,
+    pub vendor: String,
+    pub program: String,
+    pub version: String,
+    pub design_flow: Vec<SpefDesignFlow>,
+    pub divider: char,
+    pub delimiter: char,
+    pub bus_delimiter: (char, Option<char>),
+    pub time_unit: SpefUnit,
+    pub cap_unit: SpefUnit,
+    pub res_unit: SpefUnit,
+    pub induc_unit: SpefUnit,
+}
+
+impl Default for SpefHeader {
+    fn default() -> SpefHeader {
+        SpefHeader {
+            spef_version: String::new(),
+            design_name: String::new(),
+            date: String::new(),
+            vendor: String::new(),
+            program: String::new(),
+            version: String::new(),
+            design_flow: Vec::new(),
+            divider: '/',
+            delimiter: ':',
+            bus_delimiter: ('[', Some(']')),
+            time_unit: SpefUnit::new(1.0, SpefUnitName::NS),
+            cap_unit: SpefUnit::new(1.0, SpefUnitName::PF),
+            res_unit: SpefUnit::new(1.0, SpefUnitName::OHM),
+            induc_unit: SpefUnit::new(1.0, SpefUnitName::HENRY),
+        }
+    }
+}
+
+impl SpefHeader {
+    pub fn get_unit(&self, quantity: SpefQuantity) -> SpefUnit {
+        match quantity {
+            SpefQuantity::TIME => self.time_unit,
+            SpefQuantity::CAPACITANCE => self.cap_unit,
+            SpefQuantity::RESISTANCE => self.res_unit,
+            SpefQuantity::INDUCTANCE => self.induc_unit,
+        }
+    }
+
+    pub fn set_unit(&mut self, unit: SpefUnit) {
+        match unit.name.quantity() {
+            SpefQuantity::TIME => self.time_unit = unit,
+            SpefQuantity::CAPACITANCE => self.cap_unit = unit,
+            SpefQuantity::RESISTANCE => self.res_unit = unit,
+            SpefQuantity::INDUCTANCE => self.induc_unit = unit,
+        }
+    }
+
+    pub fn has_design_flow(&self, flow: &SpefDesignFlow) -> bool {
+        self.design_flow.contains(flow)
+    }
+
+    /// PIN_CAP of the design flow, INPUT_OUTPUT when the file does not say as IEEE 1481 defines
+    pub fn get_pin_cap(&self) -> SpefPinCap {
+        self.design_flow
+            .iter()
+            .find_map(|flow| match flow {
+                SpefDesignFlow::PINCAP(pin_cap) => Some(*pin_cap),
+                _ => None,
+            })
+            .unwrap_or(SpefPinCap::INPUTOUTPUT)
+    }
+
+    /// whether the net total caps already count the pin caps of the loads
+    pub fn pin_caps_included(&self) -> bool {
+        self.get_pin_cap() != SpefPinCap::NONE
+    }
+
+    /// NAME_SCOPE of the design flow, LOCAL when the file does not say
+    pub fn get_name_scope(&self) -> SpefNameScope {
+        self.design_flow
+            .iter()
+            .find_map(|flow| match flow {
+                SpefDesignFlow::NAMESCOPE(name_scope) => Some(*name_scope),
+                _ => None,
+            })
+            .unwrap_or(SpefNameScope::LOCAL)
+    }
+
+    /// COUPLING value of the design flow as written, e.g. "C", None when the file does not say
+    pub fn get_coupling(&self) -> Option<&str> {
+        self.design_flow.iter().find_map(|flow| match flow {
+            SpefDesignFlow::COUPLING(coupling) => Some(coupling.as_str()),
+            _ => None,
+        })
+    }
+
+    /// whether coupling caps were grounded, so *CAP only holds ground caps:
+    /// Some(false) for "COUPLING C", Some(true) for "COUPLING NONE",
+    /// None when the file does not say or writes a vendor value whose meaning is not known.
+    /// The parser keeps any coupling cap the file has whatever this says.
+    pub fn coupling_caps_grounded(&self) -> Option<bool> {
+        match self.get_coupling()? {
+            "C" => Some(false),
+            "NONE" => Some(true),
+            _ => None,
+        }
+    }
+
+    /// split a name into hierarchy segments and a bus index with the divider and bus delimiter of the file
+    pub fn split_name(&self, name: &str) -> SpefHierName {
+        // characters of the name with whether they were escaped
+        let mut chars: Vec<(char, bool)> = Vec::with_capacity(name.len());
+        let mut name_chars = name.chars();
+        while let Some(c) = name_chars.next() {
+            match (c, name_chars.clone().next()) {
+                ('\\', Some(escaped)) => {
+                    name_chars.next();
+                    chars.push((escaped, true));
+                }
+                _ => chars.push((c, false)),
+            }
+        }
+
+        let mut segments: Vec<Vec<(char, bool)>> = vec![Vec::new()];
+        for (c, escaped) in chars {
+            match segments.last_mut() {
+                Some(segment) if escaped || c != self.divider => segment.push((c, escaped)),
+                _ => segments.push(Vec::new()),
+            }
+        }
+        // a leading divider only says the name starts at the top
+        if segments.len() > 1 && segments[0].is_empty() {
+            segments.remove(0);
+        }
+
+        // a bus index is an unescaped prefix, digits and the suffix at the end of the last segment
+        let (prefix, suffix) = self.bus_delimiter;
+        let mut bus_index = None;
+        if let Some(leaf) = segments.last_mut() {
+            let index_end = match suffix {
+                Some(suffix) if leaf.last() == Some(&(suffix, false)) => Some(leaf.len() - 1),
+                Some(_) => None,
+                None => Some(leaf.len()),
+            };
+            let index_start = index_end.and_then(|index_end| {
+                leaf[..index_end].iter().rposition(|&(c, escaped)| c == prefix && !escaped).map(|pos| (pos, index_end))
+            });
+            if let Some((prefix_pos, index_end)) = index_start.filter(|&(prefix_pos, _)| prefix_pos > 0) {
+                let index_chars = &leaf[prefix_pos + 1..index_end];
+                if !index_chars.is_empty() && index_chars.iter().all(|&(c, escaped)| c.is_ascii_digit() && !escaped) {
+                    bus_index = index_chars.iter().map(|&(c, _)| c).collect::<String>().parse().ok();
+                    leaf.truncate(prefix_pos);
+                }
+            }
+        }
+
+        let segments = segments
+            .into_iter()
+            .map(|segment| {
+                let mut text = String::new();
+                for (c, escaped) in segment {
+                    if escaped {
+                        text.push('\\');
+                    }
+                    text.push(c);
+                }
+                text
+            })
+            .collect();
+        SpefHierName { segments, bus_index }
+    }
+
+    /// convert a value of the given quantity to SI
+    pub fn to_si(&self, quantity: SpefQuantity, value: SpefParValue) -> SpefParValue {
+        self.get_unit(quantity).to_si(value)
+    }
+}
+
+/// Store each line of Define section
+/// Define entry example: *DEFINE *12 *13 "cpu_core"
+/// instances: ["*12", "*13"]
+/// entity: "cpu_core", the design whose own spef file describes the instances
+/// A *PDEFINE line names a physical instance and is flagged as physical.
+#[derive(Clone, Debug)]
+pub struct SpefDefineEntry {
+    basic_info: SpefEntryBasicInfo,
+    pub instances: Vec<String>,
+    pub entity: String,
+    physical: bool,
+}
+
+impl SpefDefineEntry {
+    pub fn new(file_name: &Arc<str>, line_no: usize, instances: Vec<String>, entity: String) -> SpefDefineEntry {
+        SpefDefineEntry { basic_info: SpefEntryBasicInfo::new(file_name, line_no), instances, entity, physical: false }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_instances(&self) -> &[String] {
+        &self.instances
+    }
+
+    pub fn get_entity(&self) -> &str {
+        self.entity.as_str()
+    }
+
+    /// whether the line is a *PDEFINE
+    pub fn is_physical(&self) -> bool {
+        self.physical
+    }
+
+    pub fn set_physical(&mut self, physical: bool) {
+        self.physical = physical;
+    }
+}
+
+/// *S rise and fall slews of a port or a connection, with the optional thresholds they are measured at.
+/// # Examples
+/// *S 0.1 0.12 0.2 0.8
+/// rise: SpefParValue::SCALAR(0.1), fall: SpefParValue::SCALAR(0.12)
+/// thresholds: Some((SpefParValue::SCALAR(0.2), SpefParValue::SCALAR(0.8))), fractions of the supply
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpefSlews {
+    pub rise: SpefParValue,
+    pub fall: SpefParValue,
+    pub thresholds: Option<(SpefParValue, SpefParValue)>,
+}
+
+impl SpefSlews {
+    pub fn project(&self, corner: SpefCorner) -> SpefSlews {
+        SpefSlews {
+            rise: self.rise.project(corner),
+            fall: self.fall.project(corner),
+            thresholds: self.thresholds.map(|(low, high)| (low.project(corner), high.project(corner))),
+        }
+    }
+
+    /// multiply the slews, the thresholds are fractions and are kept
+    pub fn scale(&self, factor: f64) -> SpefSlews {
+        SpefSlews { rise: self.rise.scale(factor), fall: self.fall.scale(factor), thresholds: self.thresholds }
+    }
+}
+
 /// Store each line of Port section
-/// Port entry example: *37 I *C 633.84 0.242
-/// name: "37"
+/// Port entry example: *37 I *C 633.84 0.242 *L 0.0021 *S 0.1 0.12 *D sky130_fd_sc_hd__buf_2
+/// name: "*37", resolved_name: "text_out[80]" when *37 is in the name map
 /// direction: ConnectionType::INPUT
-/// coordinates: (633.84, 0.242)
+/// coordinates: Some((633.84, 0.242))
+/// load: Some(SpefParValue::SCALAR(0.0021)), the external load on the port
+/// slews: the external slews at the port
+/// driving_cell: Some("sky130_fd_sc_hd__buf_2"), the cell driving an input port
+/// The *C, *L, *S and *D attributes are optional and may come in any order.
+/// Lines of the *PHYSICAL_PORTS section are stored the same way and flagged as physical.
 #[derive(Clone, Debug)]
 pub enum ConnectionDirection {
     INPUT,
@@ -260,30 +868,104 @@ pub enum ConnectionDirection {
 pub struct SpefPortEntry {
     basic_info: SpefEntryBasicInfo,
     name: String,
+    resolved_name: String,
     direction: ConnectionDirection,
-    coordinates: (f64, f64),
+    pub coordinates: Option<(f64, f64)>,
+    pub load: Option<SpefParValue>,
+    pub slews: Option<SpefSlews>,
+    pub driving_cell: Option<String>,
+    physical: bool,
 }
 
 impl SpefPortEntry {
-    pub fn new(file_name: &str, line_no: usize, name: String, direction: ConnectionDirection, coordinates: (f64, f64)) -> SpefPortEntry {
-        SpefPortEntry { basic_info: SpefEntryBasicInfo::new(file_name, line_no), name, direction, coordinates }
+    pub fn new(file_name: &Arc<str>, line_no: usize, name: String, direction: ConnectionDirection) -> SpefPortEntry {
+        SpefPortEntry {
+            basic_info: SpefEntryBasicInfo::new(file_name, line_no),
+            resolved_name: name.clone(),
+            name,
+            direction,
+            coordinates: None,
+            load: None,
+            slews: None,
+            driving_cell: None,
+            physical: false,
+        }
     }
 
     pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
         &self.basic_info
     }
     
+    /// port name as written in the file, *37 or a plain name
     pub fn get_name(&self) -> &str {
-        &self.name.as_str()
+        self.name.as_str()
+    }
+
+    /// port name with its name map index replaced by the name
+    pub fn get_resolved_name(&self) -> &str {
+        self.resolved_name.as_str()
     }
 
     pub fn get_direction(&self) -> &ConnectionDirection {
         &self.direction
     }
 
-    pub fn get_coordinates(&self) -> (f64, f64) {
+    pub fn get_coordinates(&self) -> Option<(f64, f64)> {
         self.coordinates
     }
+
+    pub fn get_load(&self) -> Option<SpefParValue> {
+        self.load
+    }
+
+    pub fn get_slews(&self) -> Option<SpefSlews> {
+        self.slews
+    }
+
+    pub fn get_driving_cell(&self) -> Option<&str> {
+        self.driving_cell.as_deref()
+    }
+
+    pub fn set_resolved_name(&mut self, resolved_name: String) {
+        self.resolved_name = resolved_name;
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: (f64, f64)) {
+        self.coordinates = Some(coordinates);
+    }
+
+    pub fn set_load(&mut self, load: SpefParValue) {
+        self.load = Some(load);
+    }
+
+    pub fn set_slews(&mut self, slews: SpefSlews) {
+        self.slews = Some(slews);
+    }
+
+    pub fn set_driving_cell(&mut self, driving_cell: String) {
+        self.driving_cell = Some(driving_cell);
+    }
+
+    /// collapse the load and slews onto one corner
+    pub fn project_corner(&mut self, corner: SpefCorner) {
+        self.load = self.load.map(|load| load.project(corner));
+        self.slews = self.slews.map(|slews| slews.project(corner));
+    }
+
+    /// multiply the load by cap_scale and the slews by time_scale
+    pub fn scale_units(&mut self, cap_scale: f64, time_scale: f64) {
+        self.load = self.load.map(|load| load.scale(cap_scale));
+        self.slews = self.slews.map(|slews| slews.scale(time_scale));
+    }
+
+    /// whether the port comes from *PHYSICAL_PORTS
+    pub fn is_physical(&self) -> bool {
+        self.physical
+    }
+
+    pub fn set_physical(&mut self, physical: bool) {
+        self.physical = physical;
+    }
 }
 
 impl SpefEntryTrait for SpefPortEntry {
@@ -295,12 +977,126 @@ impl SpefEntryTrait for SpefPortEntry {
     }
 }
 
+/// Name of a net, an instance or a port, either its *NAME_MAP index or the name itself
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpefNameRef {
+    INDEX(usize),
+    NAME(String),
+}
+
+impl SpefNameRef {
+    /// *12 is a name map index, anything else is a name
+    pub fn from_spef_name(name: &str) -> SpefNameRef {
+        match name.strip_prefix('*').and_then(|index| index.parse::<usize>().ok()) {
+            Some(index) => SpefNameRef::INDEX(index),
+            None => SpefNameRef::NAME(name.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for SpefNameRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpefNameRef::INDEX(index) => write!(f, "*{}", index),
+            SpefNameRef::NAME(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Node of *CONN, *CAP, *RES, *INDUC and reduced net lines.
+/// # Examples
+/// *25: PORT(INDEX(25))
+/// *33272:Q: PIN(INDEX(33272), "Q")
+/// *1:2 in *D_NET *1: INTERNAL(INDEX(1), 2)
+/// *8:3 in *D_NET *1: NETNODE(INDEX(8), 3), the far side of a coupling cap
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpefNodeRef {
+    PORT(SpefNameRef),
+    PIN(SpefNameRef, String),
+    INTERNAL(SpefNameRef, usize),
+    NETNODE(SpefNameRef, usize),
+}
+
+impl SpefNodeRef {
+    /// split a node name at its last unescaped pin delimiter,
+    /// a node number on one of net_names, the index and the name of the current net, is an internal node
+    pub fn from_spef_name(name: &str, delimiter: char, net_names: &[SpefNameRef]) -> SpefNodeRef {
+        let mut delimiter_pos = None;
+        let mut escaped = false;
+        for (pos, c) in name.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == delimiter => delimiter_pos = Some(pos),
+                _ => (),
+            }
+        }
+
+        let Some(delimiter_pos) = delimiter_pos.filter(|&pos| pos > 0) else {
+            return SpefNodeRef::PORT(SpefNameRef::from_spef_name(name));
+        };
+        let owner = SpefNameRef::from_spef_name(&name[..delimiter_pos]);
+        let suffix = &name[delimiter_pos + delimiter.len_utf8()..];
+        match suffix.parse::<usize>() {
+            Ok(node) if net_names.contains(&owner) => SpefNodeRef::INTERNAL(owner, node),
+            Ok(node) => SpefNodeRef::NETNODE(owner, node),
+            Err(_) => SpefNodeRef::PIN(owner, suffix.to_string()),
+        }
+    }
+
+    /// net, instance or port the node belongs to
+    pub fn get_owner(&self) -> &SpefNameRef {
+        match self {
+            SpefNodeRef::PORT(owner)
+            | SpefNodeRef::PIN(owner, _)
+            | SpefNodeRef::INTERNAL(owner, _)
+            | SpefNodeRef::NETNODE(owner, _) => owner,
+        }
+    }
+
+    /// replace the net, instance or port of the node, e.g. to expand its name map index
+    pub fn resolve<F: Fn(&SpefNameRef) -> SpefNameRef>(&mut self, resolve_name: F) {
+        match self {
+            SpefNodeRef::PORT(owner)
+            | SpefNodeRef::PIN(owner, _)
+            | SpefNodeRef::INTERNAL(owner, _)
+            | SpefNodeRef::NETNODE(owner, _) => *owner = resolve_name(owner),
+        }
+    }
+
+    /// node name as written in a spef file with the given pin delimiter
+    pub fn to_spef_name(&self, delimiter: char) -> String {
+        match self {
+            SpefNodeRef::PORT(port) => port.to_string(),
+            SpefNodeRef::PIN(instance, pin) => format!("{}{}{}", instance, delimiter, pin),
+            SpefNodeRef::INTERNAL(net, node) | SpefNodeRef::NETNODE(net, node) => {
+                format!("{}{}{}", net, delimiter, node)
+            }
+        }
+    }
+}
+
+/// Layer and bounding box that StarRC and QRC write in a trailing comment of a conn, cap or res line,
+/// only kept when the parser is asked to read vendor annotations
+/// Annotation example: // $llx=1.2 $lly=3.4 $urx=5.6 $ury=7.8 $lvl=3
+/// layer: Some(3)
+/// ll_coordinate: Some((1.2, 3.4))
+/// ur_coordinate: Some((5.6, 7.8))
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpefVendorGeometry {
+    pub layer: Option<usize>,
+    pub ll_coordinate: Option<(f64, f64)>,
+    pub ur_coordinate: Option<(f64, f64)>,
+}
+
 /// Store each line of Conn section
 /// Conn entry example: *I *33272:Q O *C 635.66 405.835 *L 0 *D sky130_fd_sc_hd__dfxtp_1
-/// name: "37"
-/// direction: ConnectionType::INPUT
-/// coordinates: (633.84, 0.242)
-/// driving_cell: "sky130_fd_sc_hd__dfxtp_1"
+/// node: SpefNodeRef::PIN(SpefNameRef::INDEX(33272), "Q")
+/// direction: ConnectionDirection::OUTPUT
+/// coordinates: Some((635.66, 405.835))
+/// load: Some(SpefParValue::SCALAR(0.0))
+/// driving_cell: Some("sky130_fd_sc_hd__dfxtp_1")
+/// The *C, *L, *S and *D attributes are optional and may come in any order.
 #[derive(Clone, Debug)]
 pub enum ConnectionType
 {
@@ -313,45 +1109,46 @@ pub struct SpefConnEntry {
     basic_info: SpefEntryBasicInfo,
     pub conn_type: ConnectionType,
     pub conn_direction: ConnectionDirection,
-    pub name: String,
-    pub driving_cell: String,
-    pub load: f64,
-    pub layer: usize,
+    pub node: SpefNodeRef,
+    pub driving_cell: Option<String>,
+    pub load: Option<SpefParValue>,
+    pub slews: Option<SpefSlews>,
+    pub layer: Option<usize>,
 
-    pub coordinates: (f64, f64),
+    pub coordinates: Option<(f64, f64)>,
     pub ll_coordinate: (f64, f64),
     pub ur_coordinate: (f64, f64),
 }
 
 impl SpefConnEntry {
     pub fn new(
-        file_name: &str, 
-        line_no: usize, 
+        file_name: &Arc<str>,
+        line_no: usize,
         conn_type: ConnectionType,
         conn_direction: ConnectionDirection,
-        name: String,
-        driving_cell: String,
-        load: f64,
-        coordinates: (f64, f64)) -> SpefConnEntry {
-        SpefConnEntry { 
-            basic_info: SpefEntryBasicInfo::new(file_name, line_no), 
-            conn_type, 
+        node: SpefNodeRef,
+    ) -> SpefConnEntry {
+        SpefConnEntry {
+            basic_info: SpefEntryBasicInfo::new(file_name, line_no),
+            conn_type,
             conn_direction,
-            name, 
-            driving_cell, 
-            load, 
-            layer: 0, 
-            coordinates, 
-            ll_coordinate: (0.0, 0.0), 
-            ur_coordinate: (0.0, 0.0) }
+            node,
+            driving_cell: None,
+            load: None,
+            slews: None,
+            layer: None,
+            coordinates: None,
+            ll_coordinate: (0.0, 0.0),
+            ur_coordinate: (0.0, 0.0),
+        }
     }
 
     pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
         &self.basic_info
     }
     
-    pub fn get_name(&self) -> &str {
-        &self.name.as_str()
+    pub fn get_node(&self) -> &SpefNodeRef {
+        &self.node
     }
 
     pub fn get_conn_direction(&self) -> &ConnectionDirection {
@@ -362,12 +1159,40 @@ impl SpefConnEntry {
         &self.conn_type
     }
     
-    pub fn get_coordinates(&self) -> (f64, f64) {
+    pub fn get_coordinates(&self) -> Option<(f64, f64)> {
         self.coordinates
     }
 
+    pub fn get_load(&self) -> Option<SpefParValue> {
+        self.load
+    }
+
+    pub fn get_driving_cell(&self) -> Option<&str> {
+        self.driving_cell.as_deref()
+    }
+
+    pub fn get_slews(&self) -> Option<SpefSlews> {
+        self.slews
+    }
+
+    pub fn get_layer(&self) -> Option<usize> {
+        self.layer
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: (f64, f64)) {
+        self.coordinates = Some(coordinates);
+    }
+    pub fn set_load(&mut self, load: SpefParValue) {
+        self.load = Some(load);
+    }
+    pub fn set_slews(&mut self, slews: SpefSlews) {
+        self.slews = Some(slews);
+    }
+    pub fn set_driving_cell(&mut self, driving_cell: String) {
+        self.driving_cell = Some(driving_cell);
+    }
     pub fn set_layer(&mut self, layer: usize) {
-        self.layer = layer;
+        self.layer = Some(layer);
     }
     pub fn set_ll_corr(&mut self, coordinates: (f64, f64)) {
         self.ll_coordinate = coordinates;
@@ -375,6 +1200,18 @@ impl SpefConnEntry {
     pub fn set_ur_corr(&mut self, coordinates: (f64, f64)) {
         self.ur_coordinate = coordinates;
     }
+    /// fill layer and bounding box from a vendor annotation, values it does not give are left alone
+    pub fn set_vendor_geometry(&mut self, geometry: &SpefVendorGeometry) {
+        if let Some(layer) = geometry.layer {
+            self.set_layer(layer);
+        }
+        if let Some(coordinates) = geometry.ll_coordinate {
+            self.set_ll_corr(coordinates);
+        }
+        if let Some(coordinates) = geometry.ur_coordinate {
+            self.set_ur_corr(coordinates);
+        }
+    }
 }
 
 impl SpefEntryTrait for SpefConnEntry {
@@ -386,65 +1223,757 @@ impl SpefEntryTrait for SpefConnEntry {
     }
 }
 
-/// Store everthing about a net
-/// Conn entry example: 3 *1:2 0.000520945
+/// Store an internal node line of Conn section, it places an RC node of the net
+/// Internal node example: *N *1:2 *C 635.66 405.835
+/// node: SpefNodeRef::INTERNAL(SpefNameRef::INDEX(1), 2)
+/// coordinates: (635.66, 405.835)
+#[derive(Clone, Debug)]
+pub struct SpefInternalNode {
+    basic_info: SpefEntryBasicInfo,
+    pub node: SpefNodeRef,
+    pub coordinates: (f64, f64),
+}
+
+impl SpefInternalNode {
+    pub fn new(file_name: &Arc<str>, line_no: usize, node: SpefNodeRef, coordinates: (f64, f64)) -> SpefInternalNode {
+        SpefInternalNode { basic_info: SpefEntryBasicInfo::new(file_name, line_no), node, coordinates }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_node(&self) -> &SpefNodeRef {
+        &self.node
+    }
+
+    pub fn get_coordinates(&self) -> (f64, f64) {
+        self.coordinates
+    }
+}
+
+/// Store each line of Cap section
+/// Ground cap example: 1 *1:2 0.000520945
+/// Coupling cap example: 2 *1:2 *8:3 0.000106
+/// index: 2
+/// node1: SpefNodeRef::INTERNAL(SpefNameRef::INDEX(1), 2)
+/// node2: Some(SpefNodeRef::NETNODE(SpefNameRef::INDEX(8), 3)), None for a ground cap
+/// value: SpefParValue::SCALAR(0.000106)
+#[derive(Clone, Debug, PartialEq)]
+pub enum CapacitorType {
+    GROUND,
+    COUPLING,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpefCapEntry {
+    basic_info: SpefEntryBasicInfo,
+    pub index: usize,
+    pub node1: SpefNodeRef,
+    pub node2: Option<SpefNodeRef>,
+    pub value: SpefParValue,
+    pub geometry: Option<SpefVendorGeometry>,
+}
+
+impl SpefCapEntry {
+    pub fn new(
+        file_name: &Arc<str>,
+        line_no: usize,
+        index: usize,
+        node1: SpefNodeRef,
+        node2: Option<SpefNodeRef>,
+        value: SpefParValue,
+    ) -> SpefCapEntry {
+        SpefCapEntry {
+            basic_info: SpefEntryBasicInfo::new(file_name, line_no),
+            index,
+            node1,
+            node2,
+            value,
+            geometry: None,
+        }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_node1(&self) -> &SpefNodeRef {
+        &self.node1
+    }
+
+    /// the node on the other side of a coupling cap, None for a ground cap
+    pub fn get_node2(&self) -> Option<&SpefNodeRef> {
+        self.node2.as_ref()
+    }
+
+    pub fn get_value(&self) -> SpefParValue {
+        self.value
+    }
+
+    /// layer and bounding box from a vendor annotation
+    pub fn get_geometry(&self) -> Option<&SpefVendorGeometry> {
+        self.geometry.as_ref()
+    }
+
+    pub fn set_geometry(&mut self, geometry: SpefVendorGeometry) {
+        self.geometry = Some(geometry);
+    }
+
+    pub fn get_cap_type(&self) -> CapacitorType {
+        match self.node2 {
+            Some(_) => CapacitorType::COUPLING,
+            None => CapacitorType::GROUND,
+        }
+    }
+}
+
+impl SpefEntryTrait for SpefCapEntry {
+    fn is_cap_entry(&self) -> bool {
+        true
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Store each line of Res section
+/// Res entry example: 1 *1:1 *1:2 3.5
+/// index: 1
+/// node1: SpefNodeRef::INTERNAL(SpefNameRef::INDEX(1), 1)
+/// node2: SpefNodeRef::INTERNAL(SpefNameRef::INDEX(1), 2)
+/// value: SpefParValue::SCALAR(3.5)
+#[derive(Clone, Debug)]
+pub struct SpefResEntry {
+    basic_info: SpefEntryBasicInfo,
+    pub index: usize,
+    pub node1: SpefNodeRef,
+    pub node2: SpefNodeRef,
+    pub value: SpefParValue,
+    pub geometry: Option<SpefVendorGeometry>,
+}
+
+impl SpefResEntry {
+    pub fn new(
+        file_name: &Arc<str>,
+        line_no: usize,
+        index: usize,
+        node1: SpefNodeRef,
+        node2: SpefNodeRef,
+        value: SpefParValue,
+    ) -> SpefResEntry {
+        SpefResEntry {
+            basic_info: SpefEntryBasicInfo::new(file_name, line_no),
+            index,
+            node1,
+            node2,
+            value,
+            geometry: None,
+        }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_node1(&self) -> &SpefNodeRef {
+        &self.node1
+    }
+
+    pub fn get_node2(&self) -> &SpefNodeRef {
+        &self.node2
+    }
+
+    pub fn get_value(&self) -> SpefParValue {
+        self.value
+    }
+
+    /// layer and bounding box from a vendor annotation
+    pub fn get_geometry(&self) -> Option<&SpefVendorGeometry> {
+        self.geometry.as_ref()
+    }
+
+    pub fn set_geometry(&mut self, geometry: SpefVendorGeometry) {
+        self.geometry = Some(geometry);
+    }
+}
+
+impl SpefEntryTrait for SpefResEntry {
+    fn is_res_entry(&self) -> bool {
+        true
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Store each line of Induc section
+/// Induc entry example: 1 *1:1 *1:2 2.1
+/// index: 1
+/// node1: SpefNodeRef::INTERNAL(SpefNameRef::INDEX(1), 1)
+/// node2: SpefNodeRef::INTERNAL(SpefNameRef::INDEX(1), 2)
+/// value: inductance in the *L_UNIT of the header
+#[derive(Clone, Debug)]
+pub struct SpefInducEntry {
+    basic_info: SpefEntryBasicInfo,
+    pub index: usize,
+    pub node1: SpefNodeRef,
+    pub node2: SpefNodeRef,
+    pub value: SpefParValue,
+}
+
+impl SpefInducEntry {
+    pub fn new(
+        file_name: &Arc<str>,
+        line_no: usize,
+        index: usize,
+        node1: SpefNodeRef,
+        node2: SpefNodeRef,
+        value: SpefParValue,
+    ) -> SpefInducEntry {
+        SpefInducEntry { basic_info: SpefEntryBasicInfo::new(file_name, line_no), index, node1, node2, value }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_node1(&self) -> &SpefNodeRef {
+        &self.node1
+    }
+
+    pub fn get_node2(&self) -> &SpefNodeRef {
+        &self.node2
+    }
+
+    pub fn get_value(&self) -> SpefParValue {
+        self.value
+    }
+}
+
+impl SpefEntryTrait for SpefInducEntry {
+    fn is_induc_entry(&self) -> bool {
+        true
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Total resistance, ground cap and coupling cap of one metal layer, in the units of the file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpefLayerParasitics {
+    pub resistance: f64,
+    pub ground_cap: f64,
+    pub coupling_cap: f64,
+}
+
+impl SpefLayerParasitics {
+    pub fn add(&mut self, other: &SpefLayerParasitics) {
+        self.resistance += other.resistance;
+        self.ground_cap += other.ground_cap;
+        self.coupling_cap += other.coupling_cap;
+    }
+}
+
+/// Parasitics per metal layer, the None layer collects the resistors and caps without a layer annotation
+pub type SpefLayerBreakdown = BTreeMap<Option<usize>, SpefLayerParasitics>;
+
+/// Store everthing about a net
+/// Conn entry example: 3 *1:2 0.000520945
 /// name: "1:2"
 /// direction: ConnectionType::INPUT
 /// coordinates: (633.84, 0.242)
 /// driving_cell: "sky130_fd_sc_hd__dfxtp_1"
+/// A *D_PNET physical net, such as a power or ground net, is stored the same way and flagged as physical.
 
 #[derive(Clone, Debug, Default)]
 pub struct SpefNet {
     pub name: String,
     pub line_no: usize,
-    pub lcap: f64,
+    pub lcap: SpefParValue,
     connection: Vec<SpefConnEntry>,
-    caps: Vec<(String, String, f64)>,
-    ress: Vec<(String, String, f64)>,
+    internal_nodes: Vec<SpefInternalNode>,
+    caps: Vec<SpefCapEntry>,
+    ress: Vec<SpefResEntry>,
+    inducs: Vec<SpefInducEntry>,
+    physical: bool,
 }
 
 impl SpefNet {
     pub fn new(
         line_no: usize,
         name: String,
-        lcap: f64,) -> SpefNet {
-        SpefNet { name, line_no, lcap, connection: Vec::new(), caps: Vec::new(), ress: Vec::new() }
+        lcap: SpefParValue,) -> SpefNet {
+        SpefNet {
+            name,
+            line_no,
+            lcap,
+            connection: Vec::new(),
+            internal_nodes: Vec::new(),
+            caps: Vec::new(),
+            ress: Vec::new(),
+            inducs: Vec::new(),
+            physical: false,
+        }
+    }
+
+    /// whether the net comes from *D_PNET
+    pub fn is_physical(&self) -> bool {
+        self.physical
+    }
+
+    pub fn set_physical(&mut self, physical: bool) {
+        self.physical = physical;
+    }
+
+    pub fn add_connection(&mut self, conn: SpefConnEntry) {
+        self.connection.push(conn);
+    }
+
+    pub fn get_connections(&self) -> &[SpefConnEntry] {
+        &self.connection
+    }
+
+    pub fn add_internal_node(&mut self, internal_node: SpefInternalNode) {
+        self.internal_nodes.push(internal_node);
+    }
+
+    pub fn get_internal_nodes(&self) -> &[SpefInternalNode] {
+        &self.internal_nodes
+    }
+
+    /// coordinates of a node of the net, from its *N line or the *C attribute of its connection
+    pub fn get_node_coordinates(&self, node: &SpefNodeRef) -> Option<(f64, f64)> {
+        match node {
+            // the net may be named by its index or its mapped name, the node number is enough
+            SpefNodeRef::INTERNAL(_, node_index) => self
+                .internal_nodes
+                .iter()
+                .find(|internal_node| {
+                    matches!(internal_node.node, SpefNodeRef::INTERNAL(_, index) if index == *node_index)
+                })
+                .map(|internal_node| internal_node.coordinates),
+            _ => self.connection.iter().find(|conn| conn.get_node() == node).and_then(|conn| conn.coordinates),
+        }
     }
 
-    pub fn add_connection(&mut self, conn: &SpefConnEntry) {
-        self.connection.push(conn.clone());
+    /// total cap of the net at one corner with the pin caps of its connections.
+    /// The *L loads of the pins pin_cap says lcap leaves out are added to lcap.
+    pub fn get_total_cap(&self, corner: SpefCorner, pin_cap: SpefPinCap) -> f64 {
+        let pin_loads: f64 = self
+            .connection
+            .iter()
+            .filter(|conn| match pin_cap {
+                SpefPinCap::NONE => true,
+                SpefPinCap::INPUTOUTPUT => false,
+                SpefPinCap::INPUTONLY => !matches!(conn.conn_direction, ConnectionDirection::INPUT),
+            })
+            .filter_map(|conn| conn.load.map(|load| load.get_corner_value(corner)))
+            .sum();
+        self.lcap.get_corner_value(corner) + pin_loads
     }
 
-    pub fn add_cap(&mut self, cap: (String, String, f64)) {
+    /// resistance, ground cap and coupling cap of the net per layer at one corner.
+    /// A cap without its own annotation takes the layer of the connection it sits on, if that one has a layer.
+    pub fn get_layer_parasitics(&self, corner: SpefCorner) -> SpefLayerBreakdown {
+        let mut breakdown = SpefLayerBreakdown::new();
+        self.add_layer_parasitics(corner, &mut breakdown, |_| true);
+        breakdown
+    }
+
+    /// add the parasitics of the net to breakdown, leaving out the coupling caps count_coupling turns down
+    fn add_layer_parasitics(
+        &self,
+        corner: SpefCorner,
+        breakdown: &mut SpefLayerBreakdown,
+        mut count_coupling: impl FnMut(&SpefCapEntry) -> bool,
+    ) {
+        for res in self.ress.iter() {
+            let layer = res.geometry.and_then(|geometry| geometry.layer);
+            breakdown.entry(layer).or_default().resistance += res.value.get_corner_value(corner);
+        }
+        for cap in self.caps.iter() {
+            let layer = cap.geometry.and_then(|geometry| geometry.layer).or_else(|| {
+                self.connection.iter().find(|conn| conn.get_node() == cap.get_node1()).and_then(|conn| conn.layer)
+            });
+            let layer_parasitics = breakdown.entry(layer).or_default();
+            match cap.get_cap_type() {
+                CapacitorType::GROUND => layer_parasitics.ground_cap += cap.value.get_corner_value(corner),
+                CapacitorType::COUPLING if count_coupling(cap) => {
+                    layer_parasitics.coupling_cap += cap.value.get_corner_value(corner)
+                }
+                CapacitorType::COUPLING => {}
+            }
+        }
+    }
+
+    pub fn add_cap(&mut self, cap: SpefCapEntry) {
         self.caps.push(cap);
     }
 
-    pub fn add_res(&mut self, res: (String, String, f64)) {
+    pub fn get_caps(&self) -> &[SpefCapEntry] {
+        &self.caps
+    }
+
+    pub fn add_res(&mut self, res: SpefResEntry) {
         self.ress.push(res);
     }
+
+    pub fn get_ress(&self) -> &[SpefResEntry] {
+        &self.ress
+    }
+
+    pub fn add_induc(&mut self, induc: SpefInducEntry) {
+        self.inducs.push(induc);
+    }
+
+    pub fn get_inducs(&self) -> &[SpefInducEntry] {
+        &self.inducs
+    }
+
+    /// collapse every triplet of the net onto one corner
+    pub fn project_corner(&mut self, corner: SpefCorner) {
+        self.lcap = self.lcap.project(corner);
+        for conn in self.connection.iter_mut() {
+            conn.load = conn.load.map(|load| load.project(corner));
+            conn.slews = conn.slews.map(|slews| slews.project(corner));
+        }
+        for cap in self.caps.iter_mut() {
+            cap.value = cap.value.project(corner);
+        }
+        for res in self.ress.iter_mut() {
+            res.value = res.value.project(corner);
+        }
+        for induc in self.inducs.iter_mut() {
+            induc.value = induc.value.project(corner);
+        }
+    }
+
+    /// multiply caps, loads and lcap by cap_scale, resistors by res_scale, slews by time_scale
+    /// and inductors by induc_scale
+    pub fn scale_units(&mut self, cap_scale: f64, res_scale: f64, time_scale: f64, induc_scale: f64) {
+        self.lcap = self.lcap.scale(cap_scale);
+        for conn in self.connection.iter_mut() {
+            conn.load = conn.load.map(|load| load.scale(cap_scale));
+            conn.slews = conn.slews.map(|slews| slews.scale(time_scale));
+        }
+        for cap in self.caps.iter_mut() {
+            cap.value = cap.value.scale(cap_scale);
+        }
+        for res in self.ress.iter_mut() {
+            res.value = res.value.scale(res_scale);
+        }
+        for induc in self.inducs.iter_mut() {
+            induc.value = induc.value.scale(induc_scale);
+        }
+    }
+
+    /// move every line number of the net down by line_offset, for a net parsed apart from the lines before it
+    pub(crate) fn shift_line_no(&mut self, line_offset: usize) {
+        self.line_no += line_offset;
+        for conn in self.connection.iter_mut() {
+            conn.basic_info.shift_line_no(line_offset);
+        }
+        for internal_node in self.internal_nodes.iter_mut() {
+            internal_node.basic_info.shift_line_no(line_offset);
+        }
+        for cap in self.caps.iter_mut() {
+            cap.basic_info.shift_line_no(line_offset);
+        }
+        for res in self.ress.iter_mut() {
+            res.basic_info.shift_line_no(line_offset);
+        }
+        for induc in self.inducs.iter_mut() {
+            induc.basic_info.shift_line_no(line_offset);
+        }
+    }
+}
+
+/// Store one *RC line of a *LOADS block and its optional pole/residue description
+/// Rc entry example: *RC *2:A 0.0012
+///                   *Q 2 ( -1.2e9 0 ) ( -3.4e9 0 )
+///                   *K 2 ( 0.5 0 ) ( 0.5 0 )
+/// pin: SpefNodeRef::PIN(SpefNameRef::INDEX(2), "A")
+/// rc: SpefParValue::SCALAR(0.0012), the Elmore delay to the load
+#[derive(Clone, Debug)]
+pub struct SpefRcDesc {
+    basic_info: SpefEntryBasicInfo,
+    pub pin: SpefNodeRef,
+    pub rc: SpefParValue,
+    pub poles: Vec<SpefComplexValue>,
+    pub residues: Vec<SpefComplexValue>,
+}
+
+impl SpefRcDesc {
+    pub fn new(file_name: &Arc<str>, line_no: usize, pin: SpefNodeRef, rc: SpefParValue) -> SpefRcDesc {
+        SpefRcDesc {
+            basic_info: SpefEntryBasicInfo::new(file_name, line_no),
+            pin,
+            rc,
+            poles: Vec::new(),
+            residues: Vec::new(),
+        }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_pin(&self) -> &SpefNodeRef {
+        &self.pin
+    }
+
+    pub fn get_rc(&self) -> SpefParValue {
+        self.rc
+    }
+
+    pub fn get_poles(&self) -> &[SpefComplexValue] {
+        &self.poles
+    }
+
+    pub fn get_residues(&self) -> &[SpefComplexValue] {
+        &self.residues
+    }
+
+    pub fn set_poles(&mut self, poles: Vec<SpefComplexValue>) {
+        self.poles = poles;
+    }
+
+    pub fn set_residues(&mut self, residues: Vec<SpefComplexValue>) {
+        self.residues = residues;
+    }
+}
+
+/// Store one driver of a reduced net
+/// *DRIVER *1:Z
+/// *CELL sky130_fd_sc_hd__buf_2
+/// *C2_R1_C1 0.0012 23.5 0.0031
+/// *LOADS
+/// *RC ...
+/// pi_model: (c2, r1, c1) of the pi model seen by the driver
+#[derive(Clone, Debug)]
+pub struct SpefDriverReduction {
+    basic_info: SpefEntryBasicInfo,
+    pub driver: SpefNodeRef,
+    pub cell: Option<String>,
+    pub pi_model: Option<(SpefParValue, SpefParValue, SpefParValue)>,
+    loads: Vec<SpefRcDesc>,
+}
+
+impl SpefDriverReduction {
+    pub fn new(file_name: &Arc<str>, line_no: usize, driver: SpefNodeRef) -> SpefDriverReduction {
+        SpefDriverReduction {
+            basic_info: SpefEntryBasicInfo::new(file_name, line_no),
+            driver,
+            cell: None,
+            pi_model: None,
+            loads: Vec::new(),
+        }
+    }
+
+    pub fn get_basic_info(&self) -> &SpefEntryBasicInfo {
+        &self.basic_info
+    }
+
+    pub fn get_driver(&self) -> &SpefNodeRef {
+        &self.driver
+    }
+
+    pub fn get_cell(&self) -> Option<&str> {
+        self.cell.as_deref()
+    }
+
+    pub fn get_pi_model(&self) -> Option<(SpefParValue, SpefParValue, SpefParValue)> {
+        self.pi_model
+    }
+
+    pub fn set_cell(&mut self, cell: String) {
+        self.cell = Some(cell);
+    }
+
+    pub fn set_pi_model(&mut self, pi_model: (SpefParValue, SpefParValue, SpefParValue)) {
+        self.pi_model = Some(pi_model);
+    }
+
+    pub fn add_load(&mut self, load: SpefRcDesc) {
+        self.loads.push(load);
+    }
+
+    pub fn get_loads(&self) -> &[SpefRcDesc] {
+        &self.loads
+    }
+
+    pub fn get_last_load_mut(&mut self) -> Option<&mut SpefRcDesc> {
+        self.loads.last_mut()
+    }
+
+    pub fn project_corner(&mut self, corner: SpefCorner) {
+        self.pi_model = self.pi_model.map(|(c2, r1, c1)| (c2.project(corner), r1.project(corner), c1.project(corner)));
+        for load in self.loads.iter_mut() {
+            load.rc = load.rc.project(corner);
+            for pole in load.poles.iter_mut() {
+                *pole = pole.project(corner);
+            }
+            for residue in load.residues.iter_mut() {
+                *residue = residue.project(corner);
+            }
+        }
+    }
+
+    /// multiply c2/c1 by cap_scale, r1 by res_scale and *RC delays by time_scale,
+    /// poles and residues are per time so they are divided by time_scale
+    pub fn scale_units(&mut self, cap_scale: f64, res_scale: f64, time_scale: f64) {
+        self.pi_model =
+            self.pi_model.map(|(c2, r1, c1)| (c2.scale(cap_scale), r1.scale(res_scale), c1.scale(cap_scale)));
+        for load in self.loads.iter_mut() {
+            load.rc = load.rc.scale(time_scale);
+            for pole in load.poles.iter_mut() {
+                *pole = pole.scale(1.0 / time_scale);
+            }
+            for residue in load.residues.iter_mut() {
+                *residue = residue.scale(1.0 / time_scale);
+            }
+        }
+    }
+}
+
+/// Store everthing about a reduced net
+/// Reduced net example: *R_NET *1 0.0521595
+/// name: "*1"
+/// lcap: SpefParValue::SCALAR(0.0521595)
+/// drivers: one SpefDriverReduction for each *DRIVER block
+/// A *R_PNET physical net is stored the same way and flagged as physical.
+#[derive(Clone, Debug, Default)]
+pub struct SpefReducedNet {
+    pub name: String,
+    pub line_no: usize,
+    pub lcap: SpefParValue,
+    drivers: Vec<SpefDriverReduction>,
+    physical: bool,
+}
+
+impl SpefReducedNet {
+    pub fn new(line_no: usize, name: String, lcap: SpefParValue) -> SpefReducedNet {
+        SpefReducedNet { name, line_no, lcap, drivers: Vec::new(), physical: false }
+    }
+
+    /// whether the net comes from *R_PNET
+    pub fn is_physical(&self) -> bool {
+        self.physical
+    }
+
+    pub fn set_physical(&mut self, physical: bool) {
+        self.physical = physical;
+    }
+
+    pub fn add_driver(&mut self, driver: SpefDriverReduction) {
+        self.drivers.push(driver);
+    }
+
+    pub fn get_drivers(&self) -> &[SpefDriverReduction] {
+        &self.drivers
+    }
+
+    pub fn get_last_driver_mut(&mut self) -> Option<&mut SpefDriverReduction> {
+        self.drivers.last_mut()
+    }
+
+    pub fn project_corner(&mut self, corner: SpefCorner) {
+        self.lcap = self.lcap.project(corner);
+        for driver in self.drivers.iter_mut() {
+            driver.project_corner(corner);
+        }
+    }
+
+    pub fn scale_units(&mut self, cap_scale: f64, res_scale: f64, time_scale: f64) {
+        self.lcap = self.lcap.scale(cap_scale);
+        for driver in self.drivers.iter_mut() {
+            driver.scale_units(cap_scale, res_scale, time_scale);
+        }
+    }
+
+    /// move every line number of the net down by line_offset, for a net parsed apart from the lines before it
+    pub(crate) fn shift_line_no(&mut self, line_offset: usize) {
+        self.line_no += line_offset;
+        for driver in self.drivers.iter_mut() {
+            driver.basic_info.shift_line_no(line_offset);
+            for load in driver.loads.iter_mut() {
+                load.basic_info.shift_line_no(line_offset);
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 /// Spef Exchange data structure with cpp
 pub struct SpefExchange {
     file_name: SpefStringValue,
-    header: Vec<SpefHeaderEntry>,
+    header: SpefHeader,
     namemap: Vec<SpefNameMapEntry>,
+    // *NAME_MAP index to the position of its line and name to index, the names are the ones of the lines
+    namemap_positions: HashMap<usize, usize>,
+    namemap_indices: HashMap<Arc<str>, usize>,
     ports: Vec<SpefPortEntry>,
-    nets: Vec<SpefNet>
+    nets: Vec<SpefNet>,
+    reduced_nets: Vec<SpefReducedNet>,
+    power_nets: Vec<String>,
+    ground_nets: Vec<String>,
+    defines: Vec<SpefDefineEntry>,
+    instance_entities: HashMap<String, String>,
 }
 
 impl SpefExchange {
     pub fn new(
         file_name: SpefStringValue,
     ) -> SpefExchange {
-        SpefExchange { file_name, header: Vec::new(), namemap: Vec::new(), ports: Vec::new(), nets: Vec::new() }
+        SpefExchange {
+            file_name,
+            header: SpefHeader::default(),
+            namemap: Vec::new(),
+            namemap_positions: HashMap::new(),
+            namemap_indices: HashMap::new(),
+            ports: Vec::new(),
+            nets: Vec::new(),
+            reduced_nets: Vec::new(),
+            power_nets: Vec::new(),
+            ground_nets: Vec::new(),
+            defines: Vec::new(),
+            instance_entities: HashMap::new(),
+        }
     }
 
-    pub fn add_header_entry(&mut self, header: SpefHeaderEntry) {
-        self.header.push(header);
+    pub fn get_file_name(&self) -> &str {
+        self.file_name.get_str_value()
     }
+
+    pub fn set_header(&mut self, header: SpefHeader) {
+        self.header = header;
+    }
+
+    /// add a *NAME_MAP line, a later line with the same index replaces the name of the earlier one,
+    /// a name mapped twice keeps its first index
     pub fn add_namemap_entry(&mut self, namemap_entry: SpefNameMapEntry) {
+        let index = namemap_entry.get_index();
+        if let Some(old_position) = self.namemap_positions.insert(index, self.namemap.len()) {
+            let old_name = &self.namemap[old_position].name;
+            if self.namemap_indices.get(old_name) == Some(&index) {
+                self.namemap_indices.remove(old_name);
+            }
+        }
+        self.namemap_indices.entry(Arc::clone(&namemap_entry.name)).or_insert(index);
         self.namemap.push(namemap_entry);
     }
 
@@ -455,6 +1984,297 @@ impl SpefExchange {
     pub fn add_net(&mut self, net: SpefNet) {
         self.nets.push(net);
     }
+
+    pub fn get_header(&self) -> &SpefHeader {
+        &self.header
+    }
+
+    pub fn get_header_mut(&mut self) -> &mut SpefHeader {
+        &mut self.header
+    }
+
+    pub fn get_namemap(&self) -> &[SpefNameMapEntry] {
+        &self.namemap
+    }
+
+    /// name of a *NAME_MAP index, e.g. 1 -> FE_OFN0_text_out_80
+    pub fn get_name_by_index(&self, index: usize) -> Option<&str> {
+        self.namemap_positions.get(&index).map(|&position| self.namemap[position].get_name())
+    }
+
+    /// *NAME_MAP index of a name, e.g. FE_OFN0_text_out_80 -> 1
+    pub fn get_index_by_name(&self, name: &str) -> Option<usize> {
+        self.namemap_indices.get(name).copied()
+    }
+
+    /// the name behind a name map index, names and unknown indices are returned as they are
+    pub fn resolve_name_ref(&self, name_ref: &SpefNameRef) -> SpefNameRef {
+        match name_ref {
+            SpefNameRef::INDEX(index) => match self.get_name_by_index(*index) {
+                Some(name) => SpefNameRef::NAME(name.to_string()),
+                None => name_ref.clone(),
+            },
+            SpefNameRef::NAME(_) => name_ref.clone(),
+        }
+    }
+
+    /// a name as written in the file, *1 or a plain name, with its name map index replaced by the name
+    pub fn resolve_name(&self, name: &str) -> String {
+        self.resolve_name_ref(&SpefNameRef::from_spef_name(name)).to_string()
+    }
+
+    pub fn get_ports(&self) -> &[SpefPortEntry] {
+        &self.ports
+    }
+
+    pub fn get_nets(&self) -> &[SpefNet] {
+        &self.nets
+    }
+
+    pub fn add_reduced_net(&mut self, reduced_net: SpefReducedNet) {
+        self.reduced_nets.push(reduced_net);
+    }
+
+    pub fn get_reduced_nets(&self) -> &[SpefReducedNet] {
+        &self.reduced_nets
+    }
+
+    /// hand over the nets read so far, the stream reader keeps only the net it is reading
+    pub(crate) fn take_nets(&mut self) -> Vec<SpefNet> {
+        std::mem::take(&mut self.nets)
+    }
+
+    pub(crate) fn take_reduced_nets(&mut self) -> Vec<SpefReducedNet> {
+        std::mem::take(&mut self.reduced_nets)
+    }
+
+    /// number of *NAME_MAP, *PORTS and *DEFINE lines read so far
+    pub(crate) fn header_entry_counts(&self) -> [usize; 3] {
+        [self.namemap.len(), self.ports.len(), self.defines.len()]
+    }
+
+    /// move the line numbers of the *NAME_MAP, *PORTS and *DEFINE lines read after counts down by line_offset,
+    /// for lines parsed apart from the lines before them
+    pub(crate) fn shift_header_line_no(&mut self, counts: [usize; 3], line_offset: usize) {
+        let [namemap_count, port_count, define_count] = counts;
+        for namemap_entry in self.namemap[namemap_count..].iter_mut() {
+            namemap_entry.basic_info.shift_line_no(line_offset);
+        }
+        for port in self.ports[port_count..].iter_mut() {
+            port.basic_info.shift_line_no(line_offset);
+        }
+        for define in self.defines[define_count..].iter_mut() {
+            define.basic_info.shift_line_no(line_offset);
+        }
+    }
+
+    pub fn add_power_net(&mut self, net_name: String) {
+        self.power_nets.push(net_name);
+    }
+
+    pub fn add_ground_net(&mut self, net_name: String) {
+        self.ground_nets.push(net_name);
+    }
+
+    pub fn get_power_nets(&self) -> &[String] {
+        &self.power_nets
+    }
+
+    pub fn get_ground_nets(&self) -> &[String] {
+        &self.ground_nets
+    }
+
+    pub fn is_power_net(&self, net_name: &str) -> bool {
+        self.power_nets.iter().any(|power_net| power_net == net_name)
+    }
+
+    pub fn is_ground_net(&self, net_name: &str) -> bool {
+        self.ground_nets.iter().any(|ground_net| ground_net == net_name)
+    }
+
+    /// whether the net is declared in *POWER_NETS or *GROUND_NETS
+    pub fn is_supply_net(&self, net_name: &str) -> bool {
+        self.is_power_net(net_name) || self.is_ground_net(net_name)
+    }
+
+    /// add a *DEFINE/*PDEFINE line, every instance of it maps to its entity
+    pub fn add_define_entry(&mut self, define_entry: SpefDefineEntry) {
+        for instance in define_entry.get_instances() {
+            self.instance_entities.insert(instance.clone(), define_entry.get_entity().to_string());
+        }
+        self.defines.push(define_entry);
+    }
+
+    pub fn get_defines(&self) -> &[SpefDefineEntry] {
+        &self.defines
+    }
+
+    /// the entity an instance is defined as, for resolving hierarchical spef
+    pub fn get_instance_entity(&self, instance: &str) -> Option<&str> {
+        self.instance_entities.get(instance).map(|entity| entity.as_str())
+    }
+
+    /// copy of the exchange with every name map index in nets, conns, ports, caps, resistors, inductors,
+    /// reduced nets, supply nets and defines replaced by its name
+    pub fn expand_names(&self) -> SpefExchange {
+        let mut expanded = self.clone();
+        let resolve_node = |node: &mut SpefNodeRef| node.resolve(|name_ref| self.resolve_name_ref(name_ref));
+        for net in expanded.nets.iter_mut() {
+            net.name = self.resolve_name(&net.name);
+            for conn in net.connection.iter_mut() {
+                resolve_node(&mut conn.node);
+            }
+            for internal_node in net.internal_nodes.iter_mut() {
+                resolve_node(&mut internal_node.node);
+            }
+            for cap in net.caps.iter_mut() {
+                resolve_node(&mut cap.node1);
+                cap.node2.iter_mut().for_each(resolve_node);
+            }
+            for res in net.ress.iter_mut() {
+                resolve_node(&mut res.node1);
+                resolve_node(&mut res.node2);
+            }
+            for induc in net.inducs.iter_mut() {
+                resolve_node(&mut induc.node1);
+                resolve_node(&mut induc.node2);
+            }
+        }
+        for reduced_net in expanded.reduced_nets.iter_mut() {
+            reduced_net.name = self.resolve_name(&reduced_net.name);
+            for driver in reduced_net.drivers.iter_mut() {
+                resolve_node(&mut driver.driver);
+                for load in driver.loads.iter_mut() {
+                    resolve_node(&mut load.pin);
+                }
+            }
+        }
+        for port in expanded.ports.iter_mut() {
+            port.name = self.resolve_name(&port.name);
+            port.resolved_name = port.name.clone();
+        }
+        for supply_net in expanded.power_nets.iter_mut().chain(expanded.ground_nets.iter_mut()) {
+            *supply_net = self.resolve_name(supply_net);
+        }
+        for define in expanded.defines.iter_mut() {
+            for instance in define.instances.iter_mut() {
+                *instance = self.resolve_name(instance);
+            }
+        }
+        expanded.instance_entities = self
+            .instance_entities
+            .iter()
+            .map(|(instance, entity)| (self.resolve_name(instance), entity.clone()))
+            .collect();
+        expanded
+    }
+
+    /// total cap of every *D_NET and *D_PNET at one corner with the pin caps the PIN_CAP design flow leaves out,
+    /// in file order
+    pub fn get_net_total_caps(&self, corner: SpefCorner) -> Vec<(&str, f64)> {
+        let pin_cap = self.header.get_pin_cap();
+        self.nets.iter().map(|net| (net.name.as_str(), net.get_total_cap(corner, pin_cap))).collect()
+    }
+
+    /// per layer parasitics of every *D_NET and *D_PNET at one corner, in file order
+    pub fn get_net_layer_parasitics(&self, corner: SpefCorner) -> Vec<(&str, SpefLayerBreakdown)> {
+        self.nets.iter().map(|net| (net.name.as_str(), net.get_layer_parasitics(corner))).collect()
+    }
+
+    /// per layer parasitics of the whole design at one corner.
+    /// A coupling cap listed by the nets on both of its sides is counted once, by the first net that lists it:
+    /// a cap with the same nodes and value on another net is its mirror entry and uses up one earlier listing.
+    /// Parallel caps between the same nodes are all counted.
+    pub fn get_layer_parasitics(&self, corner: SpefCorner) -> SpefLayerBreakdown {
+        let mut breakdown = SpefLayerBreakdown::new();
+        // nets whose listing of a cap has not met its mirror entry yet, by the nodes and value of the cap
+        let mut unmatched_caps: HashMap<([String; 2], u64), Vec<usize>> = HashMap::new();
+        for (net_index, net) in self.nets.iter().enumerate() {
+            net.add_layer_parasitics(corner, &mut breakdown, |cap| {
+                let Some(node2) = cap.get_node2() else {
+                    return true;
+                };
+                let mut nodes = [self.resolve_node_name(cap.get_node1()), self.resolve_node_name(node2)];
+                nodes.sort();
+                let value = cap.value.get_corner_value(corner).to_bits();
+                let listing_nets = unmatched_caps.entry((nodes, value)).or_default();
+                match listing_nets.iter().position(|&listing_net| listing_net != net_index) {
+                    Some(mirror) => {
+                        listing_nets.swap_remove(mirror);
+                        false
+                    }
+                    None => {
+                        listing_nets.push(net_index);
+                        true
+                    }
+                }
+            });
+        }
+        breakdown
+    }
+
+    /// full name of a node with its name map index replaced, the same whichever net the node is seen from
+    fn resolve_node_name(&self, node: &SpefNodeRef) -> String {
+        let delimiter = self.header.delimiter;
+        match node {
+            SpefNodeRef::PORT(owner) => self.resolve_name_ref(owner).to_string(),
+            SpefNodeRef::PIN(owner, pin) => format!("{}{delimiter}{pin}", self.resolve_name_ref(owner)),
+            SpefNodeRef::INTERNAL(owner, node) | SpefNodeRef::NETNODE(owner, node) => {
+                format!("{}{delimiter}{node}", self.resolve_name_ref(owner))
+            }
+        }
+    }
+
+    /// copy of the exchange with every min:typ:max triplet collapsed onto one corner,
+    /// so one file can feed the min, typ and max analyses
+    pub fn project_corner(&self, corner: SpefCorner) -> SpefExchange {
+        let mut projected = self.clone();
+        for net in projected.nets.iter_mut() {
+            net.project_corner(corner);
+        }
+        for reduced_net in projected.reduced_nets.iter_mut() {
+            reduced_net.project_corner(corner);
+        }
+        for port in projected.ports.iter_mut() {
+            port.project_corner(corner);
+        }
+        projected
+    }
+
+    /// copy of the exchange with every value rewritten in the given units and the header updated to match.
+    /// Units not listed are kept, a unit whose multiplier is not a positive number is a UNIT error.
+    /// # Examples
+    /// exchange.convert_units(&[SpefUnit::new(1.0, SpefUnitName::FF), SpefUnit::new(1.0, SpefUnitName::KOHM)])?
+    pub fn convert_units(&self, units: &[SpefUnit]) -> Result<SpefExchange, SpefError> {
+        let mut converted = self.clone();
+        for unit in units {
+            if !(unit.multiplier > 0.0 && unit.multiplier.is_finite()) {
+                let text = format!("{} {}", unit.multiplier, unit.name.keyword());
+                let message = "Unit multiplier must be a positive number";
+                return Err(SpefError::UNIT(SpefErrorInfo::new(&self.file_name.value, 0, 0, &text, message)));
+            }
+            converted.header.set_unit(*unit);
+        }
+
+        let scale = |quantity: SpefQuantity| {
+            self.header.get_unit(quantity).si_scale() / converted.header.get_unit(quantity).si_scale()
+        };
+        let cap_scale = scale(SpefQuantity::CAPACITANCE);
+        let res_scale = scale(SpefQuantity::RESISTANCE);
+        let time_scale = scale(SpefQuantity::TIME);
+        let induc_scale = scale(SpefQuantity::INDUCTANCE);
+
+        for net in converted.nets.iter_mut() {
+            net.scale_units(cap_scale, res_scale, time_scale, induc_scale);
+        }
+        for reduced_net in converted.reduced_nets.iter_mut() {
+            reduced_net.scale_units(cap_scale, res_scale, time_scale);
+        }
+        for port in converted.ports.iter_mut() {
+            port.scale_units(cap_scale, time_scale);
+        }
+        Ok(converted)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -467,5 +2287,239 @@ pub enum SpefParserData {
     PortEntry(SpefPortEntry),
     ConnEntry(SpefConnEntry),
     NetEntry(SpefNet),
-    Exchange(SpefExchange)
+    Exchange(Box<SpefExchange>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::spef_stream::SpefStreamReader;
+    use super::super::SpefParseOptions;
+    use super::*;
+
+    const HEADER: &str = "*SPEF \"IEEE 1481-1998\"
+*DESIGN \"data_test\"
+*DIVIDER /
+*DELIMITER :
+*BUS_DELIMITER []
+*T_UNIT 1 NS
+*C_UNIT 1 PF
+*R_UNIT 1 OHM
+*L_UNIT 1 HENRY
+";
+
+    fn read_spef(text: &str) -> Result<SpefExchange, SpefError> {
+        SpefStreamReader::new("test.spef", text.as_bytes(), SpefParseOptions::default()).read_all()
+    }
+
+    #[test]
+    fn convert_units_scales_every_value_and_the_header() {
+        let net = "*D_NET n1 2
+*CONN
+*I u1:A I *L 0.01 *S 0.1 0.2
+*CAP
+1 n1:1 0.5
+*RES
+1 n1:1 u1:A 1500
+*INDUC
+1 n1:1 u1:A 1
+*END
+";
+        let exchange = read_spef(&format!("{HEADER}{net}")).unwrap();
+        let units = [
+            SpefUnit::new(1.0, SpefUnitName::PS),
+            SpefUnit::new(1.0, SpefUnitName::FF),
+            SpefUnit::new(1.0, SpefUnitName::KOHM),
+            SpefUnit::new(1.0, SpefUnitName::UH),
+        ];
+        let converted = exchange.convert_units(&units).unwrap();
+        for unit in units {
+            assert_eq!(converted.get_header().get_unit(unit.name.quantity()), unit);
+        }
+
+        let close =
+            |value: SpefParValue, expected: f64| (value.get_corner_value(SpefCorner::TYP) - expected).abs() < 1e-9;
+        let net = &converted.get_nets()[0];
+        assert!(close(net.lcap, 2000.0));
+        let conn = &net.get_connections()[0];
+        assert!(close(conn.get_load().unwrap(), 10.0));
+        let slews = conn.get_slews().unwrap();
+        assert!(close(slews.rise, 100.0) && close(slews.fall, 200.0));
+        assert!(close(net.get_caps()[0].get_value(), 500.0));
+        assert!(close(net.get_ress()[0].get_value(), 1.5));
+        assert!(close(net.get_inducs()[0].get_value(), 1e6));
+
+        // the source is left as it was
+        assert!(close(exchange.get_nets()[0].get_caps()[0].get_value(), 0.5));
+    }
+
+    #[test]
+    fn convert_units_rejects_a_zero_multiplier() {
+        let exchange = read_spef(HEADER).unwrap();
+        let result = exchange.convert_units(&[SpefUnit::new(0.0, SpefUnitName::FF)]);
+        assert!(matches!(result, Err(SpefError::UNIT(_))));
+    }
+
+    #[test]
+    fn split_name_keeps_escapes_and_finds_the_bus_index() {
+        let exchange = read_spef(HEADER).unwrap();
+        let header = exchange.get_header();
+        let split = |name: &str| {
+            let hier_name = header.split_name(name);
+            (hier_name.segments, hier_name.bus_index)
+        };
+        assert_eq!(split("u1/u2/data\\[0\\][3]"), (vec!["u1".into(), "u2".into(), "data\\[0\\]".into()], Some(3)));
+        // an escaped divider stays in its segment
+        assert_eq!(split("u1\\/u2/n1"), (vec!["u1\\/u2".into(), "n1".into()], None));
+        // a leading divider only says the name starts at the top
+        assert_eq!(split("/top/n1[2]"), (vec!["top".into(), "n1".into()], Some(2)));
+        assert_eq!(split("n1[a]"), (vec!["n1[a]".into()], None));
+
+        let custom_header =
+            HEADER.replace("*DIVIDER /", "*DIVIDER .").replace("*BUS_DELIMITER []", "*BUS_DELIMITER <>");
+        let exchange = read_spef(&custom_header).unwrap();
+        let header = exchange.get_header();
+        let hier_name = header.split_name("a.b<7>");
+        assert_eq!(
+            (hier_name.get_path(), hier_name.get_leaf(), hier_name.bus_index),
+            (&["a".into()][..], "b", Some(7))
+        );
+        assert_eq!(header.split_name("a/b[7]").segments, ["a/b[7]"]);
+    }
+
+    #[test]
+    fn name_map_rejects_an_index_or_a_name_mapped_twice() {
+        for (name_map, message) in [
+            ("*1 n1\n*1 n2\n", "Name map index *1 is already defined"),
+            ("*1 n1\n*2 n1\n", "Name n1 is already mapped to *1"),
+            ("*1 n1\n*D_NET *2 1\n*END\n", "*2 is not in the name map"),
+        ] {
+            let error = read_spef(&format!("{HEADER}*NAME_MAP\n{name_map}")).unwrap_err();
+            assert!(format!("{error:?}").contains(message), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn expand_names_replaces_every_index() {
+        let text = format!(
+            "{HEADER}*NAME_MAP
+*1 n1
+*2 u1
+*3 out
+*4 VDD
+*POWER_NETS *4
+*PORTS
+*3 O
+*D_NET *1 1
+*CONN
+*I *2:A I
+*P *3 O
+*CAP
+1 *1:1 0.5
+*RES
+1 *1:1 *2:A 2
+*END
+"
+        );
+        let exchange = read_spef(&text).unwrap();
+        assert_eq!(exchange.get_name_by_index(2), Some("u1"));
+        assert_eq!(exchange.get_index_by_name("out"), Some(3));
+        assert_eq!(exchange.resolve_name("*4"), "VDD");
+        assert_eq!(exchange.resolve_name("n9"), "n9");
+
+        let expanded = exchange.expand_names();
+        let name = |name: &str| SpefNameRef::NAME(name.to_string());
+        assert_eq!(expanded.get_power_nets(), ["VDD"]);
+        assert_eq!(expanded.get_ports()[0].get_name(), "out");
+        let net = &expanded.get_nets()[0];
+        assert_eq!(net.name, "n1");
+        assert_eq!(net.get_connections()[0].get_node(), &SpefNodeRef::PIN(name("u1"), "A".to_string()));
+        assert_eq!(net.get_connections()[1].get_node(), &SpefNodeRef::PORT(name("out")));
+        assert_eq!(net.get_caps()[0].get_node1(), &SpefNodeRef::INTERNAL(name("n1"), 1));
+        assert_eq!(net.get_ress()[0].get_node2(), &SpefNodeRef::PIN(name("u1"), "A".to_string()));
+        // the source keeps its indices
+        assert_eq!(exchange.get_nets()[0].name, "*1");
+    }
+
+    #[test]
+    fn name_map_and_ports_read_a_direction_by_the_section() {
+        let text = format!("{HEADER}*NAME_MAP\n*5 B\n*6 I\n*7 B[0]\n*PORTS\n*5 B\n*6 I *C 1 2\n*7 O\n");
+        let exchange = read_spef(&text).unwrap();
+        assert_eq!(exchange.get_name_by_index(5), Some("B"));
+        assert_eq!(exchange.get_name_by_index(6), Some("I"));
+        assert_eq!(exchange.get_name_by_index(7), Some("B[0]"));
+        let ports: Vec<_> = exchange
+            .get_ports()
+            .iter()
+            .map(|port| format!("{} {:?}", port.get_resolved_name(), port.get_direction()))
+            .collect();
+        assert_eq!(ports, ["B INOUT", "I INPUT", "B[0] OUTPUT"]);
+
+        // port attributes make the line a port even in *NAME_MAP, and a name is no port
+        let error = read_spef(&format!("{HEADER}*NAME_MAP\n*5 B *C 1 2\n")).unwrap_err();
+        assert!(format!("{error:?}").contains("*PORTS"), "{error:?}");
+        let error = read_spef(&format!("{HEADER}*NAME_MAP\n*5 n5\n*PORTS\n*5 n5\n")).unwrap_err();
+        assert!(format!("{error:?}").contains("*NAME_MAP"), "{error:?}");
+    }
+
+    #[test]
+    fn layer_parasitics_count_coupling_caps_once() {
+        let nets = "*D_NET a 1
+*CAP
+1 a:1 0.5
+2 a:1 b:1 0.1
+3 a:1 b:1 0.2
+4 a:1 d:1 0.1
+5 a:1 d:1 0.1
+*END
+*D_NET b 1
+*CAP
+1 b:1 a:1 0.1
+2 b:1 a:1 0.2
+3 b:1 c:1 0.05
+*END
+";
+        let exchange = read_spef(&format!("{HEADER}{nets}")).unwrap();
+        let breakdown = exchange.get_layer_parasitics(SpefCorner::TYP);
+        // two-sided 0.1 and 0.2, one-sided 0.05 and two parallel one-sided 0.1
+        assert!((breakdown[&None].coupling_cap - 0.55).abs() < 1e-12);
+        assert!((breakdown[&None].ground_cap - 0.5).abs() < 1e-12);
+
+        let net_breakdowns = exchange.get_net_layer_parasitics(SpefCorner::TYP);
+        assert!((net_breakdowns[0].1[&None].coupling_cap - 0.5).abs() < 1e-12);
+        assert!((net_breakdowns[1].1[&None].coupling_cap - 0.35).abs() < 1e-12);
+    }
+
+    #[test]
+    fn coupling_flow_says_whether_caps_were_grounded() {
+        let net = "*D_NET n1 1\n*CAP\n1 n1:1 n2:1 0.1\n*END\n";
+        for (design_flow, grounded) in [
+            ("", None),
+            ("*DESIGN_FLOW \"COUPLING C\"\n", Some(false)),
+            ("*DESIGN_FLOW \"COUPLING NONE\"\n", Some(true)),
+            ("*DESIGN_FLOW \"COUPLING X\"\n", None),
+        ] {
+            let exchange = read_spef(&format!("{HEADER}{design_flow}{net}")).unwrap();
+            assert_eq!(exchange.get_header().coupling_caps_grounded(), grounded, "{design_flow}");
+            assert_eq!(exchange.get_nets()[0].get_caps()[0].get_cap_type(), CapacitorType::COUPLING);
+        }
+    }
+
+    #[test]
+    fn total_caps_add_the_pin_caps_pin_cap_leaves_out() {
+        let net = "*D_NET n1 1
+*CONN
+*P a I *L 0.5
+*I u1:Z O *L 0.25
+*END
+";
+        for (design_flow, total_cap) in [
+            ("", 1.0),
+            ("*DESIGN_FLOW \"PIN_CAP INPUT_OUTPUT\"\n", 1.0),
+            ("*DESIGN_FLOW \"PIN_CAP INPUT_ONLY\"\n", 1.25),
+            ("*DESIGN_FLOW \"PIN_CAP NONE\"\n", 1.75),
+        ] {
+            let exchange = read_spef(&format!("{HEADER}{design_flow}{net}")).unwrap();
+            assert_eq!(exchange.get_net_total_caps(SpefCorner::TYP), [("n1", total_cap)], "{design_flow}");
+        }
+    }
 }
@@ -0,0 +1,88 @@
+#![allow(clippy::upper_case_acronyms)]
+
+use std::fmt;
+
+/// Where an error was found and what the parser was looking at.
+/// # Examples
+/// aes_simple.spef:56:1 `*CAP`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpefErrorInfo {
+    pub file_name: String,
+    pub line_no: usize,
+    pub column: usize,
+    pub text: String,
+    pub message: String,
+}
+
+impl SpefErrorInfo {
+    pub fn new(file_name: &str, line_no: usize, column: usize, text: &str, message: &str) -> SpefErrorInfo {
+        SpefErrorInfo {
+            file_name: file_name.to_string(),
+            line_no,
+            column,
+            text: text.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Errors raised while reading a spef file.
+/// IO: the file cannot be read
+/// SYNTAX: the text does not match the spef grammar
+/// SEMANTIC: the text is well formed but does not make sense, e.g. a *CAP section after *RES
+/// UNIT: a unit or a value that cannot be scaled
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpefError {
+    IO(SpefErrorInfo),
+    SYNTAX(SpefErrorInfo),
+    SEMANTIC(SpefErrorInfo),
+    UNIT(SpefErrorInfo),
+}
+
+impl SpefError {
+    pub fn get_info(&self) -> &SpefErrorInfo {
+        match self {
+            SpefError::IO(info) | SpefError::SYNTAX(info) | SpefError::SEMANTIC(info) | SpefError::UNIT(info) => info,
+        }
+    }
+
+    /// move the error down by line_offset lines, for text parsed apart from the lines before it
+    pub(crate) fn shift_line_no(mut self, line_offset: usize) -> SpefError {
+        match &mut self {
+            SpefError::IO(info) | SpefError::SYNTAX(info) | SpefError::SEMANTIC(info) | SpefError::UNIT(info) => {
+                info.line_no += line_offset
+            }
+        }
+        self
+    }
+
+    pub fn get_kind_name(&self) -> &'static str {
+        match self {
+            SpefError::IO(_) => "io",
+            SpefError::SYNTAX(_) => "syntax",
+            SpefError::SEMANTIC(_) => "semantic",
+            SpefError::UNIT(_) => "unit",
+        }
+    }
+}
+
+impl fmt::Display for SpefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.get_info();
+        write!(
+            f,
+            "{}:{}:{}: {} error: {}",
+            info.file_name,
+            info.line_no,
+            info.column,
+            self.get_kind_name(),
+            info.message
+        )?;
+        if !info.text.is_empty() {
+            write!(f, " `{}`", info.text.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SpefError {}
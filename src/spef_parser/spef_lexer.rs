@@ -0,0 +1,686 @@
+use super::spef_data::{self, SectionType, SpefNameRef, SpefNodeRef, SpefParValue};
+use super::{parse_vendor_annotation, ConnAttributes, SpefParserState};
+
+/// The most tokens a *D_NET line has: *I pin dir *C x y *L load *S rise fall low high *D cell
+const MAX_LINE_TOKENS: usize = 16;
+
+/// A chunk the hand-written parser does not take, the pest grammar reads it instead and reports its errors
+#[derive(Debug)]
+pub(crate) struct Fallback;
+
+/// One line of a chunk cut into its tokens, all borrowed from the chunk
+struct SpefLine<'a> {
+    line_no: usize,
+    tokens: [&'a str; MAX_LINE_TOKENS],
+    token_count: usize,
+    // the text after the last token, where a vendor annotation may be
+    rest: &'a str,
+}
+
+impl<'a> SpefLine<'a> {
+    fn tokens(&self) -> &[&'a str] {
+        &self.tokens[..self.token_count]
+    }
+}
+
+/// Byte tokenizer over the lines of a chunk, tokens are split at spaces and tabs and a // comment ends the line.
+/// A line the grammar could read another way, such as a token running into a comment, is a Fallback.
+struct SpefLexer<'a> {
+    text: &'a str,
+    pos: usize,
+    line_no: usize,
+}
+
+impl<'a> SpefLexer<'a> {
+    fn new(text: &'a str) -> SpefLexer<'a> {
+        SpefLexer { text, pos: 0, line_no: 0 }
+    }
+
+    /// next line with at least one token, None at the end of the chunk
+    fn next_line(&mut self) -> Result<Option<SpefLine<'a>>, Fallback> {
+        while self.pos < self.text.len() {
+            let bytes = &self.text.as_bytes()[self.pos..];
+            let line_len = bytes.iter().position(|&byte| byte == b'\n').unwrap_or(bytes.len());
+            let line = &self.text[self.pos..self.pos + line_len];
+            self.pos += line_len + 1;
+            self.line_no += 1;
+
+            let spef_line = tokenize_line(self.line_no, line)?;
+            if spef_line.token_count > 0 {
+                return Ok(Some(spef_line));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn tokenize_line(line_no: usize, line: &str) -> Result<SpefLine<'_>, Fallback> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let bytes = line.as_bytes();
+    let mut spef_line = SpefLine { line_no, tokens: [""; MAX_LINE_TOKENS], token_count: 0, rest: "" };
+    let mut pos = 0;
+    let mut last_token_end = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b' ' | b'\t' => pos += 1,
+            // pest takes a lone carriage return for a newline
+            b'\r' => return Err(Fallback),
+            _ if bytes[pos..].starts_with(b"//") => break,
+            _ => {
+                let end = bytes[pos..]
+                    .iter()
+                    .position(|byte| matches!(byte, b' ' | b'\t'))
+                    .map_or(bytes.len(), |end| pos + end);
+                let token = &line[pos..end];
+                if token.contains("//") || spef_line.token_count == MAX_LINE_TOKENS {
+                    return Err(Fallback);
+                }
+                spef_line.tokens[spef_line.token_count] = token;
+                spef_line.token_count += 1;
+                pos = end;
+                last_token_end = end;
+            }
+        }
+    }
+    spef_line.rest = &line[last_token_end..];
+    Ok(spef_line)
+}
+
+/// float of the grammar: [+-]digits[.digits][(e|E)[+-]digits]
+fn is_float(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    let skip_digits = |pos: usize| pos + bytes[pos..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+
+    let int_start = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    let mut pos = skip_digits(int_start);
+    if pos == int_start {
+        return false;
+    }
+    if bytes.get(pos) == Some(&b'.') {
+        pos = skip_digits(pos + 1);
+    }
+    if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+        let exp_start = pos + 1 + usize::from(matches!(bytes.get(pos + 1), Some(b'+' | b'-')));
+        pos = skip_digits(exp_start);
+        if pos == exp_start {
+            return false;
+        }
+    }
+    pos == bytes.len()
+}
+
+fn parse_float(token: &str) -> Result<f64, Fallback> {
+    if !is_float(token) {
+        return Err(Fallback);
+    }
+    token.parse::<f64>().map_err(|_| Fallback)
+}
+
+/// one number or a min:typ:max triplet
+fn parse_par_value(token: &str) -> Result<SpefParValue, Fallback> {
+    let mut values = token.split(':');
+    match (values.next(), values.next(), values.next(), values.next()) {
+        (Some(value), None, None, None) => Ok(SpefParValue::SCALAR(parse_float(value)?)),
+        (Some(min), Some(typ), Some(max), None) => {
+            Ok(SpefParValue::TRIPLET(parse_float(min)?, parse_float(typ)?, parse_float(max)?))
+        }
+        _ => Err(Fallback),
+    }
+}
+
+fn parse_index(token: &str) -> Result<usize, Fallback> {
+    if token.is_empty() || !token.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(Fallback);
+    }
+    token.parse::<usize>().map_err(|_| Fallback)
+}
+
+fn is_special_char(byte: u8) -> bool {
+    b"!\"#$%&'()*+,-./:;<=>?@[\\]^`{|}~".contains(&byte)
+}
+
+/// node_char+ of the grammar: letters, digits, _, hierarchy and bus characters and escaped special characters
+fn is_node_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' if bytes.get(pos + 1).is_some_and(|&byte| is_special_char(byte)) => pos += 2,
+            byte if byte.is_ascii_alphanumeric() || b"_./:|[]{}()<>".contains(&byte) => pos += 1,
+            _ => return false,
+        }
+    }
+    !bytes.is_empty()
+}
+
+/// net_ref of the grammar, a name map index such as *12 or a name
+fn is_net_ref(name: &str) -> bool {
+    match name.strip_prefix('*') {
+        Some(index) => !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()),
+        None => is_node_name(name),
+    }
+}
+
+/// pin_port of the grammar, a name map index with an optional :pin or :node, or a name
+fn is_pin_port(name: &str) -> bool {
+    match name.strip_prefix('*') {
+        Some(rest) => {
+            let digits = rest.bytes().take_while(|byte| byte.is_ascii_digit()).count();
+            digits > 0 && (digits == rest.len() || is_node_name(&rest[digits..]))
+        }
+        None => is_node_name(name),
+    }
+}
+
+fn parse_direction(token: &str) -> Result<spef_data::ConnectionDirection, Fallback> {
+    match token {
+        "I" => Ok(spef_data::ConnectionDirection::INPUT),
+        "O" => Ok(spef_data::ConnectionDirection::OUTPUT),
+        "B" => Ok(spef_data::ConnectionDirection::INOUT),
+        _ => Err(Fallback),
+    }
+}
+
+/// *C, *L, *S and *D attributes of a *CONN line, each at most once
+fn parse_conn_attributes(mut tokens: &[&str]) -> Result<ConnAttributes, Fallback> {
+    let mut attributes = ConnAttributes::default();
+    while !tokens.is_empty() {
+        let duplicated = match tokens {
+            ["*C", x, y, rest @ ..] => {
+                tokens = rest;
+                attributes.coordinates.replace((parse_float(x)?, parse_float(y)?)).is_some()
+            }
+            ["*L", load, rest @ ..] => {
+                tokens = rest;
+                attributes.load.replace(parse_par_value(load)?).is_some()
+            }
+            ["*S", rise, fall, rest @ ..] => {
+                // the thresholds are there when two more values follow
+                let thresholds = match rest {
+                    [low, high, ..] => parse_par_value(low).and_then(|low| Ok((low, parse_par_value(high)?))).ok(),
+                    _ => None,
+                };
+                tokens = if thresholds.is_some() { &rest[2..] } else { rest };
+                let slews =
+                    spef_data::SpefSlews { rise: parse_par_value(rise)?, fall: parse_par_value(fall)?, thresholds };
+                attributes.slews.replace(slews).is_some()
+            }
+            ["*D", cell, rest @ ..] if is_node_name(cell) => {
+                tokens = rest;
+                attributes.driving_cell.replace(cell.to_string()).is_some()
+            }
+            _ => return Err(Fallback),
+        };
+        if duplicated {
+            return Err(Fallback);
+        }
+    }
+    Ok(attributes)
+}
+
+/// What the lines of one *D_NET need from the parser state
+struct SpefNetContext<'a> {
+    parser_state: &'a SpefParserState,
+    delimiter: char,
+    // the net by its name map index and by its name, borrowed from the chunk or the name map
+    net_index: Option<usize>,
+    net_name: Option<&'a str>,
+}
+
+impl<'a> SpefNetContext<'a> {
+    fn new(parser_state: &'a SpefParserState, net_ref: &'a str) -> SpefNetContext<'a> {
+        let exchange_data = &parser_state.exchange_data;
+        let (net_index, net_name) = match net_ref.strip_prefix('*').and_then(|index| index.parse::<usize>().ok()) {
+            Some(index) => (Some(index), exchange_data.get_name_by_index(index)),
+            None => (exchange_data.get_index_by_name(net_ref), Some(net_ref)),
+        };
+        let delimiter = exchange_data.get_header().delimiter;
+        SpefNetContext { parser_state, delimiter, net_index, net_name }
+    }
+
+    fn is_net(&self, name: &SpefNameRef) -> bool {
+        match name {
+            SpefNameRef::INDEX(index) => self.net_index == Some(*index),
+            SpefNameRef::NAME(name) => self.net_name == Some(name.as_str()),
+        }
+    }
+
+    /// a name map index has to be in the *NAME_MAP section
+    fn check_name(&self, name: &SpefNameRef) -> Result<(), Fallback> {
+        match name {
+            SpefNameRef::INDEX(index) if self.parser_state.exchange_data.get_name_by_index(*index).is_none() => {
+                Err(Fallback)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn node(&self, token: &str) -> Result<SpefNodeRef, Fallback> {
+        if !is_pin_port(token) {
+            return Err(Fallback);
+        }
+        // a node number on another net is an internal node when that net is this one by its index or name
+        let node = match SpefNodeRef::from_spef_name(token, self.delimiter, &[]) {
+            SpefNodeRef::NETNODE(owner, node) if self.is_net(&owner) => SpefNodeRef::INTERNAL(owner, node),
+            node => node,
+        };
+        self.check_name(node.get_owner())?;
+        Ok(node)
+    }
+
+    fn vendor_geometry(&self, line: &SpefLine) -> Result<Option<spef_data::SpefVendorGeometry>, Fallback> {
+        if !self.parser_state.options.vendor_annotations {
+            return Ok(None);
+        }
+        parse_vendor_annotation(line.rest).map_err(|_| Fallback)
+    }
+
+    fn parse_conn_line(&self, line: &SpefLine, net: &mut spef_data::SpefNet) -> Result<(), Fallback> {
+        let file_name = &self.parser_state.file_name;
+        match line.tokens() {
+            ["*N", node, "*C", x, y] => {
+                let node = match self.node(node)? {
+                    node @ SpefNodeRef::INTERNAL(..) => node,
+                    _ => return Err(Fallback),
+                };
+                let coordinates = (parse_float(x)?, parse_float(y)?);
+                net.add_internal_node(spef_data::SpefInternalNode::new(file_name, line.line_no, node, coordinates));
+            }
+            [conn_type @ ("*P" | "*I"), pin, direction, attribute_tokens @ ..] => {
+                let (conn_type, node) = if *conn_type == "*P" {
+                    if !is_pin_port(pin) {
+                        return Err(Fallback);
+                    }
+                    let port = SpefNameRef::from_spef_name(pin);
+                    self.check_name(&port)?;
+                    (spef_data::ConnectionType::EXTERNAL, SpefNodeRef::PORT(port))
+                } else {
+                    let node = match self.node(pin)? {
+                        SpefNodeRef::PORT(_) => return Err(Fallback),
+                        SpefNodeRef::INTERNAL(instance, pin) | SpefNodeRef::NETNODE(instance, pin) => {
+                            SpefNodeRef::PIN(instance, pin.to_string())
+                        }
+                        node => node,
+                    };
+                    (spef_data::ConnectionType::INTERNAL, node)
+                };
+                let direction = parse_direction(direction)?;
+                let attributes = parse_conn_attributes(attribute_tokens)?;
+
+                let mut conn = spef_data::SpefConnEntry::new(file_name, line.line_no, conn_type, direction, node);
+                conn.coordinates = attributes.coordinates;
+                conn.load = attributes.load;
+                conn.slews = attributes.slews;
+                conn.driving_cell = attributes.driving_cell;
+                if let Some(geometry) = self.vendor_geometry(line)? {
+                    conn.set_vendor_geometry(&geometry);
+                }
+                net.add_connection(conn);
+            }
+            _ => return Err(Fallback),
+        }
+        Ok(())
+    }
+
+    fn parse_cap_line(&self, line: &SpefLine, net: &mut spef_data::SpefNet) -> Result<(), Fallback> {
+        let (index, node1, node2, value) = match line.tokens() {
+            [index, node1, value] => (index, node1, None, value),
+            [index, node1, node2, value] => (index, node1, Some(node2), value),
+            _ => return Err(Fallback),
+        };
+        let node2 = node2.map(|node2| self.node(node2)).transpose()?;
+        let file_name = &self.parser_state.file_name;
+        let (index, node1, value) = (parse_index(index)?, self.node(node1)?, parse_par_value(value)?);
+        let mut cap = spef_data::SpefCapEntry::new(file_name, line.line_no, index, node1, node2, value);
+        if let Some(geometry) = self.vendor_geometry(line)? {
+            cap.set_geometry(geometry);
+        }
+        net.add_cap(cap);
+        Ok(())
+    }
+
+    /// index, both nodes and the value of a *RES or *INDUC line
+    fn parse_two_node_line(
+        &self,
+        line: &SpefLine,
+    ) -> Result<(usize, SpefNodeRef, SpefNodeRef, SpefParValue), Fallback> {
+        match line.tokens() {
+            [index, node1, node2, value] => {
+                Ok((parse_index(index)?, self.node(node1)?, self.node(node2)?, parse_par_value(value)?))
+            }
+            _ => Err(Fallback),
+        }
+    }
+
+    fn parse_res_line(&self, line: &SpefLine, net: &mut spef_data::SpefNet) -> Result<(), Fallback> {
+        let file_name = &self.parser_state.file_name;
+        let (index, node1, node2, value) = self.parse_two_node_line(line)?;
+        let mut res = spef_data::SpefResEntry::new(file_name, line.line_no, index, node1, node2, value);
+        if let Some(geometry) = self.vendor_geometry(line)? {
+            res.set_geometry(geometry);
+        }
+        net.add_res(res);
+        Ok(())
+    }
+
+    fn parse_induc_line(&self, line: &SpefLine, net: &mut spef_data::SpefNet) -> Result<(), Fallback> {
+        let file_name = &self.parser_state.file_name;
+        let (index, node1, node2, value) = self.parse_two_node_line(line)?;
+        net.add_induc(spef_data::SpefInducEntry::new(file_name, line.line_no, index, node1, node2, value));
+        Ok(())
+    }
+}
+
+/// read a chunk of one *NAME_MAP line without pest, line_no is the line number of the chunk in the file.
+/// Lines the grammar would read another way or turn down, such as an index mapped twice, are a Fallback.
+pub(crate) fn parse_namemap_chunk(
+    chunk: &str,
+    line_no: usize,
+    parser_state: &SpefParserState,
+) -> Result<spef_data::SpefNameMapEntry, Fallback> {
+    if chunk.contains("/*") || parser_state.current_section != SectionType::NAMEMAP {
+        return Err(Fallback);
+    }
+
+    let mut lexer = SpefLexer::new(chunk);
+    let line = lexer.next_line()?.ok_or(Fallback)?;
+    let (index, name) = match line.tokens() {
+        [index, name] => (index.strip_prefix('*').ok_or(Fallback).and_then(parse_index)?, *name),
+        _ => return Err(Fallback),
+    };
+    if !is_node_name(name) || lexer.next_line()?.is_some() {
+        return Err(Fallback);
+    }
+    let exchange_data = &parser_state.exchange_data;
+    if exchange_data.get_name_by_index(index).is_some() || exchange_data.get_index_by_name(name).is_some() {
+        return Err(Fallback);
+    }
+    Ok(spef_data::SpefNameMapEntry::new(&parser_state.file_name, line_no + line.line_no - 1, index, name))
+}
+
+/// read a chunk of one *D_NET up to its *END without pest, with the line numbers counted from the chunk start.
+/// It gives the same net as the grammar, anything the grammar would read another way or turn down is a Fallback.
+pub(crate) fn parse_dnet_chunk(chunk: &str, parser_state: &SpefParserState) -> Result<spef_data::SpefNet, Fallback> {
+    // a /* comment may span lines and hide entries
+    if chunk.contains("/*") || !SectionType::DNET.can_follow(&parser_state.current_section) {
+        return Err(Fallback);
+    }
+
+    let mut lexer = SpefLexer::new(chunk);
+    let line = lexer.next_line()?.ok_or(Fallback)?;
+    let (net_ref, mut net) = match line.tokens() {
+        [keyword @ ("*D_NET" | "*D_PNET"), name, lcap] if is_net_ref(name) => {
+            let mut net = spef_data::SpefNet::new(line.line_no, name.to_string(), parse_par_value(lcap)?);
+            net.set_physical(*keyword == "*D_PNET");
+            (*name, net)
+        }
+        _ => return Err(Fallback),
+    };
+
+    let context = SpefNetContext::new(parser_state, net_ref);
+    // an index net has to be in the *NAME_MAP section
+    if net_ref.starts_with('*') && context.net_name.is_none() {
+        return Err(Fallback);
+    }
+
+    let mut section = SectionType::DNET;
+    while let Some(line) = lexer.next_line()? {
+        if let [keyword] = line.tokens() {
+            let next_section = match *keyword {
+                "*CONN" => SectionType::CONN,
+                "*CAP" => SectionType::CAP,
+                "*RES" => SectionType::RES,
+                "*INDUC" => SectionType::INDUC,
+                "*END" => SectionType::END,
+                _ => return Err(Fallback),
+            };
+            if !next_section.can_follow(&section) {
+                return Err(Fallback);
+            }
+            section = next_section;
+            if section == SectionType::END {
+                break;
+            }
+            continue;
+        }
+        match section {
+            SectionType::CONN => context.parse_conn_line(&line, &mut net)?,
+            SectionType::CAP => context.parse_cap_line(&line, &mut net)?,
+            SectionType::RES => context.parse_res_line(&line, &mut net)?,
+            SectionType::INDUC => context.parse_induc_line(&line, &mut net)?,
+            _ => return Err(Fallback),
+        }
+    }
+
+    // the chunk has to end with the *END of the net
+    if section != SectionType::END || lexer.next_line()?.is_some() {
+        return Err(Fallback);
+    }
+    Ok(net)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::spef_data::{SpefExchange, SpefStringValue};
+    use super::super::spef_error::SpefError;
+    use super::super::spef_stream::SpefStreamReader;
+    use super::super::{pest_error_to_spef_error, Rule, SpefParseOptions, SpefParser, SpefParserState};
+    use super::{parse_dnet_chunk, parse_namemap_chunk};
+    use pest::Parser;
+
+    const HEADER: &str = r#"*SPEF "IEEE 1481-1998"
+*DESIGN "lexer_test"
+*DIVIDER /
+*DELIMITER :
+*BUS_DELIMITER []
+*T_UNIT 1 NS
+*C_UNIT 1 PF
+*R_UNIT 1 OHM
+*L_UNIT 1 UH
+
+*NAME_MAP
+*1 n1
+*2 u1
+*3 n2
+*4 in1
+*5 out1
+
+*PORTS
+*4 I *C 0 0
+*5 O
+"#;
+
+    const INDEX_NET: &str = "*D_NET *1 0.5:0.6:0.7
+*CONN
+*P *4 I *C 1 2 *L 0.1
+*I *2:A O *C 3.5 -4e-1 *L 0.2 *S 0.1 0.2 0.5:0.5:0.5 0.6 *D INVX1
+*I *2:3 I
+*N *1:3 *C 5 6
+*CAP
+1 *1:3 0.25 // ground
+2 *1:3 *3:1 1e-3
+3 *4 +2.5E+1
+*RES
+1 *4 *1:3 12.5
+2 *1:3 *2:A 3.
+*INDUC
+1 *4 *1:3 2.0
+*END
+";
+
+    const NAME_NET: &str = r"
+// nets may name their nodes by the name map alias
+*D_NET n2 1
+*CONN
+*I u1/a\[3\]:Z B *S 1 2 *D BUF\$1
+*P bus[0] I
+*CAP
+1 n2:1 *3:2 0.1
+2 n2:1 bus[0] 0.2
+*RES
+1 n2:1 u1/a\[3\]:Z 4
+*END
+";
+
+    const PHYSICAL_NET: &str = "// supply\r\n\r\n*D_PNET VDD 2\r\n*CAP\r\n1\tVDD:1\t0.5\r\n*END\r\n";
+
+    const VENDOR_NET: &str = "*D_NET *1 0.3
+*CONN
+*P *4 I *L 0.2 // $lvl=0
+*I *2:A I // $llx=1 $lly=2 $urx=3 $ury=4 $lvl=2
+*I *2:Z O *S 0.1 0.2 // $lvl=1
+*CAP
+1 *1:1 0.1 // $lvl=3 $a=0.2
+*RES
+1 *2:A *1:1 2 //$llx=0 $lly=0 $urx=1e-3 $ury=2 $lvl=5
+*END
+";
+
+    /// parser state after reading the text through the pest grammar
+    fn read_with_pest(text: &str, options: SpefParseOptions) -> Result<SpefParserState, SpefError> {
+        let exchange_data = SpefExchange::new(SpefStringValue { value: "test.spef".to_string() });
+        let mut parser_state = SpefParserState::new("test.spef", options, exchange_data);
+        let spef_entries =
+            SpefParser::parse(Rule::spef_file, text).map_err(|err| pest_error_to_spef_error("test.spef", err))?;
+        for entry in spef_entries {
+            parser_state.process_entry(entry)?;
+        }
+        Ok(parser_state)
+    }
+
+    /// the text before the first net and the nets up to their *END
+    fn split_nets(text: &str) -> (&str, Vec<&str>) {
+        let header_end = text.find("\n*D_").map_or(text.len(), |pos| pos + 1);
+        let mut nets = Vec::new();
+        let mut rest = &text[header_end..];
+        while let Some(end_pos) = rest.find("*END") {
+            let net_end = rest[end_pos..].find('\n').map_or(rest.len(), |line_end| end_pos + line_end + 1);
+            nets.push(&rest[..net_end]);
+            rest = &rest[net_end..];
+        }
+        (&text[..header_end], nets)
+    }
+
+    /// every net has to go through the hand-written parser and come out as pest reads it
+    fn assert_same_nets(text: &str, options: SpefParseOptions) {
+        let (header, nets) = split_nets(text);
+        let mut parser_state = read_with_pest(header, options).unwrap();
+        for net_chunk in nets {
+            let net = parse_dnet_chunk(net_chunk, &parser_state).expect("net falls back to pest");
+            for entry in SpefParser::parse(Rule::spef_file, net_chunk).unwrap() {
+                parser_state.process_entry(entry).unwrap();
+            }
+            let pest_net = parser_state.exchange_data.take_nets().pop().unwrap();
+            assert_eq!(format!("{net:?}"), format!("{pest_net:?}"));
+        }
+    }
+
+    /// the whole text through the stream reader and through the pest grammar alone
+    fn assert_same_exchange(text: &str, options: SpefParseOptions) {
+        let exchange = SpefStreamReader::new("test.spef", text.as_bytes(), options).read_all();
+        let pest_exchange = read_with_pest(text, options).map(|parser_state| parser_state.exchange_data);
+        match (exchange, pest_exchange) {
+            (Ok(exchange), Ok(pest_exchange)) => {
+                assert_eq!(format!("{:?}", exchange.get_header()), format!("{:?}", pest_exchange.get_header()));
+                assert_eq!(format!("{:?}", exchange.get_namemap()), format!("{:?}", pest_exchange.get_namemap()));
+                assert_eq!(format!("{:?}", exchange.get_ports()), format!("{:?}", pest_exchange.get_ports()));
+                assert_eq!(format!("{:?}", exchange.get_defines()), format!("{:?}", pest_exchange.get_defines()));
+                assert_eq!(format!("{:?}", exchange.get_nets()), format!("{:?}", pest_exchange.get_nets()));
+                assert_eq!(
+                    format!("{:?}", exchange.get_reduced_nets()),
+                    format!("{:?}", pest_exchange.get_reduced_nets())
+                );
+            }
+            (Err(err), Err(pest_err)) => assert_eq!(err, pest_err),
+            (result, pest_result) => panic!("{:?} is read as {:?} by pest", result.err(), pest_result.err()),
+        }
+    }
+
+    #[test]
+    fn index_names_match_pest() {
+        let text = format!("{HEADER}{INDEX_NET}");
+        assert_same_nets(&text, SpefParseOptions::default());
+        assert_same_exchange(&text, SpefParseOptions::default());
+    }
+
+    #[test]
+    fn literal_and_escaped_names_match_pest() {
+        let text = format!("{HEADER}{NAME_NET}");
+        assert_same_nets(&text, SpefParseOptions::default());
+        assert_same_exchange(&text, SpefParseOptions::default());
+    }
+
+    #[test]
+    fn physical_net_with_crlf_and_tabs_matches_pest() {
+        let text = format!("{HEADER}{PHYSICAL_NET}");
+        assert_same_nets(&text, SpefParseOptions::default());
+        assert_same_exchange(&text, SpefParseOptions::default());
+    }
+
+    #[test]
+    fn vendor_annotations_match_pest() {
+        let text = format!("{HEADER}{VENDOR_NET}{INDEX_NET}");
+        for vendor_annotations in [false, true] {
+            assert_same_nets(&text, SpefParseOptions { vendor_annotations });
+            assert_same_exchange(&text, SpefParseOptions { vendor_annotations });
+        }
+    }
+
+    #[test]
+    fn aes_simple_matches_pest() {
+        let text = include_str!("../../aes_simple.spef");
+        assert_same_nets(text, SpefParseOptions::default());
+        assert_same_exchange(text, SpefParseOptions::default());
+    }
+
+    #[test]
+    fn unusual_nets_fall_back_to_pest() {
+        let nets = [
+            // a block comment may span lines
+            "*D_NET *1 0.3\n*CONN\n*I *2:A I /* driver\n*/\n*END\n",
+            // errors come from the grammar
+            "*D_NET *1 0.3\n*CONN\n*I *2:A X\n*END\n",
+            "*D_NET *1 0.3\n*CONN\n*I *9:A I\n*END\n",
+            "*D_NET *1 0.3\n*CONN\n*I *2:A I *S 1 2 3\n*END\n",
+            "*D_NET *1 0.3\n*CONN\n*I *2:A I *L 1 *L 2\n*END\n",
+            "*D_NET *1 0.3\n*CONN\n1 *1:1 0.5\n*END\n",
+            "*D_NET *1 0.3\n*RES\n1 *1:1 0.5\n*END\n",
+            "*D_NET *1 0.3\n*CAP\n1 *1:1 0.5\n*CONN\n*END\n",
+            "*D_NET *1 0.3\n*CAP\n1 *1:1 .5\n*END\n",
+        ];
+        for net_chunk in nets {
+            let parser_state = read_with_pest(HEADER, SpefParseOptions::default()).unwrap();
+            assert!(parse_dnet_chunk(net_chunk, &parser_state).is_err(), "{net_chunk}");
+            assert_same_exchange(&format!("{HEADER}{net_chunk}"), SpefParseOptions::default());
+        }
+    }
+
+    #[test]
+    fn namemap_lines_match_pest() {
+        let namemap_end = HEADER.find("*5 out1\n").unwrap() + "*5 out1\n".len();
+        let parser_state = read_with_pest(&HEADER[..namemap_end], SpefParseOptions::default()).unwrap();
+        // a line after *5 and whether the hand-written parser takes it
+        let lines = [
+            ("*6 u1/a\\[3\\]\n", true),
+            ("\t*6 Out1 // output\r\n", true),
+            ("*6 B0\n", true),
+            ("*6 n6 /* net */\n", false),
+            ("*6 I\n", true),
+            ("*6 B[0]\n", true),
+            ("*6 B *C 1 2\n", false),
+            ("*6 n1\n", false),
+            ("*1 n6\n", false),
+            ("*6 n6 n7\n", false),
+        ];
+        for (line, taken) in lines {
+            assert_eq!(parse_namemap_chunk(line, 17, &parser_state).is_ok(), taken, "{line}");
+            let text = format!("{}{line}{}{INDEX_NET}", &HEADER[..namemap_end], &HEADER[namemap_end..]);
+            assert_same_exchange(&text, SpefParseOptions::default());
+        }
+    }
+
+    #[test]
+    fn net_without_end_matches_pest() {
+        assert_same_exchange(&format!("{HEADER}*D_NET *1 0.3\n*CAP\n1 *1:1 0.5\n"), SpefParseOptions::default());
+    }
+}
@@ -0,0 +1,322 @@
+use super::spef_data;
+use super::spef_error::{SpefError, SpefErrorInfo};
+use super::spef_lexer;
+use super::{pest_error_to_spef_error, Rule, SpefParseOptions, SpefParser, SpefParserState};
+use pest::Parser;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// One net handed out by the stream reader, with all of its sections
+#[derive(Clone, Debug)]
+pub enum SpefStreamNet {
+    NET(spef_data::SpefNet),
+    REDUCEDNET(spef_data::SpefReducedNet),
+}
+
+/// Spef reader for files that do not fit in memory.
+/// The header, *NAME_MAP, *POWER_NETS, *GROUND_NETS, *PORTS and *DEFINE sections are read first and kept,
+/// one line at a time, then the reader yields one *D_NET or *R_NET at a time,
+/// so memory is bounded by the largest net besides the kept sections.
+/// # Examples
+/// let mut reader = SpefStreamReader::open("aes_simple.spef", SpefParseOptions::default())?;
+/// let delimiter = reader.read_header_sections()?.get_header().delimiter;
+/// for net in reader { ... }
+pub struct SpefStreamReader<R: BufRead> {
+    reader: R,
+    parser_state: SpefParserState,
+    // lines handed to the parser so far
+    lines_read: usize,
+    // the last line read, and the lines of the header entry or the net being read
+    line: String,
+    chunk: String,
+    // line holds the *D_NET or *R_NET line that ended the header sections
+    pending_line: bool,
+    // a /* comment is open at the end of the last line read
+    in_comment: bool,
+    header_sections_read: bool,
+    // the nets cannot be read without the name map and units, so an error there is kept and handed out again
+    header_error: Option<SpefError>,
+    finished: bool,
+}
+
+impl SpefStreamReader<BufReader<File>> {
+    pub fn open(spef_file_path: &str, options: SpefParseOptions) -> Result<Self, SpefError> {
+        match File::open(spef_file_path) {
+            Ok(file) => Ok(SpefStreamReader::new(spef_file_path, BufReader::new(file), options)),
+            Err(err) => Err(SpefError::IO(SpefErrorInfo::new(spef_file_path, 0, 0, "", &err.to_string()))),
+        }
+    }
+}
+
+impl<R: BufRead> SpefStreamReader<R> {
+    pub fn new(file_name: &str, reader: R, options: SpefParseOptions) -> SpefStreamReader<R> {
+        let exchange_data = spef_data::SpefExchange::new(spef_data::SpefStringValue { value: file_name.to_string() });
+        SpefStreamReader {
+            reader,
+            parser_state: SpefParserState::new(file_name, options, exchange_data),
+            lines_read: 0,
+            line: String::new(),
+            chunk: String::new(),
+            pending_line: false,
+            in_comment: false,
+            header_sections_read: false,
+            header_error: None,
+            finished: false,
+        }
+    }
+
+    /// read everything before the first net, the exchange holds no nets.
+    /// An error in these sections is returned again on every later call and the reader yields no nets.
+    pub fn read_header_sections(&mut self) -> Result<&spef_data::SpefExchange, SpefError> {
+        if let Some(err) = &self.header_error {
+            return Err(err.clone());
+        }
+        if !self.header_sections_read {
+            if let Err(err) = self.read_header_lines() {
+                self.header_error = Some(err.clone());
+                return Err(err);
+            }
+            self.header_sections_read = true;
+        }
+        Ok(&self.parser_state.exchange_data)
+    }
+
+    /// header, name map, ports and defines read so far
+    pub fn get_exchange(&self) -> &spef_data::SpefExchange {
+        &self.parser_state.exchange_data
+    }
+
+    /// read the nets left in the file into the exchange, as parse_spef_file does
+    pub fn read_all(mut self) -> Result<spef_data::SpefExchange, SpefError> {
+        let mut nets = Vec::new();
+        let mut reduced_nets = Vec::new();
+        for net in self.by_ref() {
+            match net? {
+                SpefStreamNet::NET(net) => nets.push(net),
+                SpefStreamNet::REDUCEDNET(reduced_net) => reduced_nets.push(reduced_net),
+            }
+        }
+
+        let mut exchange_data = self.parser_state.exchange_data;
+        for net in nets {
+            exchange_data.add_net(net);
+        }
+        for reduced_net in reduced_nets {
+            exchange_data.add_reduced_net(reduced_net);
+        }
+        Ok(exchange_data)
+    }
+
+    /// run the header lines up to the first net through the parser
+    fn read_header_lines(&mut self) -> Result<(), SpefError> {
+        loop {
+            let first_line_no = self.read_chunk()?;
+            if self.chunk.is_empty() {
+                return Ok(());
+            }
+            // most header lines are *NAME_MAP lines, the hand-written parser takes them and pest the rest
+            if let Ok(namemap_entry) = spef_lexer::parse_namemap_chunk(&self.chunk, first_line_no, &self.parser_state) {
+                self.parser_state.exchange_data.add_namemap_entry(namemap_entry);
+                continue;
+            }
+            let counts = self.parser_state.exchange_data.header_entry_counts();
+            self.parse_chunk(first_line_no)?;
+            self.parser_state.exchange_data.shift_header_line_no(counts, first_line_no - 1);
+        }
+    }
+
+    /// read the next header line, with the lines of a /* comment it opens, or the next net up to its *END
+    /// into chunk. Returns the line number of the first line, chunk is empty after the header sections
+    /// and at the end of the file.
+    fn read_chunk(&mut self) -> Result<usize, SpefError> {
+        let first_line_no = self.lines_read + 1;
+        self.chunk.clear();
+        loop {
+            let keyword = if self.pending_line {
+                self.pending_line = false;
+                None
+            } else {
+                self.line.clear();
+                match self.reader.read_line(&mut self.line) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(err) => {
+                        let file_name = &self.parser_state.file_name;
+                        let info = SpefErrorInfo::new(file_name, self.lines_read + 1, 0, "", &err.to_string());
+                        return Err(SpefError::IO(info));
+                    }
+                }
+                first_word(&self.line, &mut self.in_comment)
+            };
+            if !self.header_sections_read && matches!(keyword, Some("*D_NET" | "*D_PNET" | "*R_NET" | "*R_PNET")) {
+                self.pending_line = true;
+                break;
+            }
+            self.lines_read += 1;
+            self.chunk.push_str(&self.line);
+            let chunk_end = if self.header_sections_read { keyword == Some("*END") } else { !self.in_comment };
+            if chunk_end {
+                break;
+            }
+        }
+        Ok(first_line_no)
+    }
+
+    /// run the lines of chunk through the parser state machine, line numbers are kept as in the file
+    fn parse_chunk(&mut self, first_line_no: usize) -> Result<(), SpefError> {
+        let line_offset = first_line_no - 1;
+        let spef_entries = match SpefParser::parse(Rule::spef_file, &self.chunk) {
+            Ok(spef_entries) => spef_entries,
+            Err(err) => {
+                return Err(pest_error_to_spef_error(&self.parser_state.file_name, err).shift_line_no(line_offset))
+            }
+        };
+        for entry in spef_entries {
+            self.parser_state.process_entry(entry).map_err(|err| err.shift_line_no(line_offset))?;
+        }
+        Ok(())
+    }
+
+    fn read_next_net(&mut self) -> Result<Option<SpefStreamNet>, SpefError> {
+        self.read_header_sections()?;
+        loop {
+            let first_line_no = self.read_chunk()?;
+            if self.chunk.is_empty() {
+                return Ok(None);
+            }
+            // most *D_NET blocks go through the hand-written parser, anything it does not take goes to pest
+            if let Ok(mut net) = spef_lexer::parse_dnet_chunk(&self.chunk, &self.parser_state) {
+                self.parser_state.current_section = spef_data::SectionType::END;
+                net.shift_line_no(first_line_no - 1);
+                return Ok(Some(SpefStreamNet::NET(net)));
+            }
+            self.parse_chunk(first_line_no)?;
+            // a chunk ends at *END, so it closes one net at most, comments after the last net close none
+            if let Some(mut net) = self.parser_state.exchange_data.take_nets().pop() {
+                net.shift_line_no(first_line_no - 1);
+                return Ok(Some(SpefStreamNet::NET(net)));
+            }
+            if let Some(mut reduced_net) = self.parser_state.exchange_data.take_reduced_nets().pop() {
+                reduced_net.shift_line_no(first_line_no - 1);
+                return Ok(Some(SpefStreamNet::REDUCEDNET(reduced_net)));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SpefStreamReader<R> {
+    type Item = Result<SpefStreamNet, SpefError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let next_net = self.read_next_net().transpose();
+        // stop after the last net or the first error
+        self.finished = !matches!(next_net, Some(Ok(_)));
+        next_net
+    }
+}
+
+/// first word of a line outside of comments and strings, in_comment carries an open /* comment to the next line
+fn first_word<'a>(line: &'a str, in_comment: &mut bool) -> Option<&'a str> {
+    let bytes = line.as_bytes();
+    let mut word = None;
+    let mut in_string = false;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if *in_comment {
+            if bytes[pos..].starts_with(b"*/") {
+                *in_comment = false;
+                pos += 1;
+            }
+        } else if in_string {
+            in_string = bytes[pos] != b'"';
+        } else if bytes[pos..].starts_with(b"//") {
+            break;
+        } else if bytes[pos..].starts_with(b"/*") {
+            *in_comment = true;
+            pos += 1;
+        } else if bytes[pos] == b'"' {
+            in_string = true;
+        } else if word.is_none() && !bytes[pos].is_ascii_whitespace() {
+            let end =
+                bytes[pos..].iter().position(|byte| byte.is_ascii_whitespace()).map_or(bytes.len(), |end| pos + end);
+            // a comment may follow the word without a space
+            let text = &line[pos..end];
+            word = Some(text.split("//").next().unwrap_or(text).split("/*").next().unwrap_or(text));
+        }
+        pos += 1;
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Read};
+
+    const HEADER: &str = "*SPEF \"IEEE 1481-1998\"
+*DESIGN \"stream_test\"
+*DIVIDER /
+*DELIMITER :
+*BUS_DELIMITER []
+*T_UNIT 1 NS
+*C_UNIT 1 PF
+*R_UNIT 1 OHM
+*L_UNIT 1 HENRY
+*NAME_MAP
+*1 n1
+*2 n2
+";
+    const NETS: &str = "*D_NET *1 1
+*CAP
+1 *1:1 0.5
+*END
+*D_NET *2 2
+*CAP
+1 *2:1 0.5
+*END
+";
+
+    // a reader that fails once the text before it has been read
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read past the nets"))
+        }
+    }
+
+    #[test]
+    fn nets_are_yielded_before_the_rest_of_the_file_is_read() {
+        let text = format!("{HEADER}{NETS}");
+        let reader = BufReader::new(text.as_bytes().chain(FailingReader));
+        let mut stream = SpefStreamReader::new("test.spef", reader, SpefParseOptions::default());
+        assert_eq!(stream.read_header_sections().unwrap().get_namemap().len(), 2);
+
+        for (name, line_no) in [("*1", 13), ("*2", 17)] {
+            match stream.next() {
+                Some(Ok(SpefStreamNet::NET(net))) => assert_eq!((net.name.as_str(), net.line_no), (name, line_no)),
+                other => panic!("expected net {name}, got {other:?}"),
+            }
+        }
+        assert!(stream.get_exchange().get_nets().is_empty());
+        assert!(matches!(stream.next(), Some(Err(SpefError::IO(_)))));
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn nothing_is_yielded_after_an_error_in_the_header_sections() {
+        let text = format!("{}{NETS}", HEADER.replace("*2 n2", "*1 n2"));
+        let mut stream = SpefStreamReader::new("test.spef", text.as_bytes(), SpefParseOptions::default());
+        let error = match stream.next() {
+            Some(Err(error @ SpefError::SEMANTIC(_))) => error,
+            other => panic!("expected the duplicate index, got {other:?}"),
+        };
+        assert!(stream.next().is_none());
+        // the nets after it are not read, however the reader is asked
+        assert_eq!(stream.read_header_sections().unwrap_err(), error);
+        assert!(stream.next().is_none());
+    }
+}